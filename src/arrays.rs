@@ -170,6 +170,15 @@ impl ArrayBoard<i16> {
             self.set(sq, self.at(sq) + n);
         }
     }
+
+    pub const fn sum(&self, m: BoardMask) -> i32 {
+        let mut it = Squares(m);
+        let mut res = 0;
+        while let Some(sq) = it.next() {
+            res += self.at(sq) as i32;
+        }
+        res
+    }
 }
 
 impl ArrayBoard<i8> {
@@ -187,4 +196,13 @@ impl ArrayBoard<i8> {
             self.set(sq, self.at(sq) + n);
         }
     }
+
+    pub const fn sum(&self, m: BoardMask) -> i32 {
+        let mut it = Squares(m);
+        let mut res = 0;
+        while let Some(sq) = it.next() {
+            res += self.at(sq) as i32;
+        }
+        res
+    }
 }
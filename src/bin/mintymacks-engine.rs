@@ -0,0 +1,6 @@
+use mintymacks::engine::ucio::UciServer;
+
+#[tokio::main]
+async fn main() -> tokio::io::Result<()> {
+    UciServer::new().run().await
+}
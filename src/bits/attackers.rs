@@ -39,6 +39,34 @@ impl HalfBitBoard {
         res.add(self.queens, scale * ChessPiece::QUEEN);
     }
 
+    pub fn materiel_by(&self, value: impl Fn(ChessPiece) -> i16, res: &mut ArrayBoard<i16>) {
+        res.add(self.pawns, value(ChessPiece::Pawn));
+        res.add(self.knights, value(ChessPiece::Knight));
+        res.add(self.bishops, value(ChessPiece::Bishop));
+        res.add(self.rooks, value(ChessPiece::Rook));
+        res.add(self.queens, value(ChessPiece::Queen));
+        res.add(self.kings, value(ChessPiece::King));
+    }
+
+    pub fn count_piece_attackers(
+        &self,
+        p: ChessPiece,
+        c: Color,
+        amount: i8,
+        enemy: BoardMask,
+        res: &mut ArrayBoard<i8>,
+    ) {
+        let total = self.total | enemy;
+        match p {
+            ChessPiece::Pawn => count_pawn_attackers(self.pawns, c, amount, res),
+            ChessPiece::Knight => count_knight_attackers(self.knights, amount, res),
+            ChessPiece::Bishop => count_bishop_attackers(self.bishops, total, amount, res),
+            ChessPiece::Rook => count_rook_attackers(self.rooks, total, amount, res),
+            ChessPiece::Queen => count_queen_attackers(self.queens, total, amount, res),
+            ChessPiece::King => count_king_attackers(self.kings, amount, res),
+        }
+    }
+
     pub fn count_attackers(
         &self,
         c: Color,
@@ -34,6 +34,21 @@ impl HalfBitBoard {
             | queen_attacks(self.queens, total);
     }
 
+    /// Pieces of this side attacking `sq` given the occupancy,
+    /// so removing pieces from `occupied` reveals x-ray attackers
+    pub fn attackers_to(&self, c: Color, sq: Square, occupied: BoardMask) -> BoardMask {
+        let target = sq.bit();
+        let diagonal = (self.bishops | self.queens) & occupied;
+        let orthogonal = (self.rooks | self.queens) & occupied;
+
+        (pawn_attacks(target, c.opposite()) & self.pawns
+            | KNIGHT_MOVES.at(sq) & self.knights
+            | KING_MOVES.at(sq) & self.kings
+            | bishop_rays(sq, occupied) & diagonal
+            | rook_rays(sq, occupied) & orthogonal)
+            & occupied
+    }
+
     pub fn attacks_after_enemy_move(
         &self,
         c: Color,
@@ -70,6 +70,17 @@ impl HalfBitBoard {
         }
     }
 
+    pub const fn mask(&self, p: ChessPiece) -> BoardMask {
+        match p {
+            ChessPiece::Pawn => self.pawns,
+            ChessPiece::Knight => self.knights,
+            ChessPiece::Bishop => self.bishops,
+            ChessPiece::Rook => self.rooks,
+            ChessPiece::Queen => self.queens,
+            ChessPiece::King => self.kings,
+        }
+    }
+
     pub const fn render_to(&self, color: Color, board: &mut ArrayBoard<Option<ColoredChessPiece>>) {
         board.set_mask(self.pawns, Some(color.piece(ChessPiece::Pawn)));
         board.set_mask(self.knights, Some(color.piece(ChessPiece::Knight)));
@@ -0,0 +1,210 @@
+use crate::{
+    arrays::ArrayBoard,
+    bits::{
+        BoardMask, Squares,
+        attacks::pawn_attacks,
+        board::{BitBoard, HalfBitBoard},
+        fills::{
+            black_pawn_attack_fill, black_pawn_move_fill, white_pawn_attack_fill,
+            white_pawn_move_fill,
+        },
+        jumps::{KING_MOVES, KNIGHT_MOVES},
+        movegen::{encode_pawn_move, encode_piece_move},
+        one_bit,
+    },
+    bits2::{bishop_rays, queen_rays, rook_rays},
+    model::{ChessPiece, Color, Square, metadata::Metadata, moves::ChessMove},
+};
+
+/// Checks and pins around both kings, from the point of view of the side to move
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckInfo {
+    /// Enemy pieces giving check
+    pub checkers: BoardMask,
+    /// Friendly pieces that cannot leave the line between their king and an enemy slider
+    pub pinned: BoardMask,
+    /// For each pinned piece, the squares it may still move to: up to and including the pinner
+    pub pin_rays: ArrayBoard<BoardMask>,
+    /// Friendly pieces whose moving off the line would uncover a check on the enemy king
+    pub discoverers: BoardMask,
+    /// Squares from which each friendly piece type would attack the enemy king
+    pub check_squares: [BoardMask; 6],
+}
+
+impl CheckInfo {
+    pub const fn check_squares(&self, piece: ChessPiece) -> BoardMask {
+        self.check_squares[piece as usize - 1]
+    }
+}
+
+impl BitBoard {
+    pub fn check_info(&self) -> CheckInfo {
+        let c = self.metadata.to_move;
+        let (friendly, enemy) = self.active_passive(c);
+        let total = friendly.total | enemy.total;
+
+        let mut res = CheckInfo {
+            checkers: checkers(friendly, enemy, c),
+            pinned: 0,
+            pin_rays: ArrayBoard::new(0),
+            discoverers: 0,
+            check_squares: [0; 6],
+        };
+
+        if let Some(king) = Squares(friendly.kings).next() {
+            for (blocker, ray) in blockers(king, enemy, total) {
+                if friendly.total & blocker.bit() != 0 {
+                    res.pinned |= blocker.bit();
+                    res.pin_rays.set(blocker, ray);
+                }
+            }
+        }
+
+        if let Some(king) = Squares(enemy.kings).next() {
+            for (blocker, _) in blockers(king, friendly, total) {
+                res.discoverers |= blocker.bit() & friendly.total;
+            }
+
+            let bishop = bishop_rays(king, total);
+            let rook = rook_rays(king, total);
+            res.check_squares = [
+                pawn_attacks(king.bit(), c.opposite()),
+                KNIGHT_MOVES.at(king),
+                bishop,
+                rook,
+                bishop | rook,
+                0,
+            ];
+        }
+
+        res
+    }
+
+    /// Every legal move when the side to move is in check, and nothing otherwise
+    pub fn evasions(&self, res: &mut Vec<ChessMove>) {
+        let (act, pas) = self.active_passive(self.metadata.to_move);
+        let checkers = checkers(act, pas, self.metadata.to_move);
+        if checkers != 0 {
+            legal_evasions(act, pas, self.metadata, checkers, res);
+        }
+    }
+}
+
+/// Enemy pieces attacking the friendly king
+#[inline]
+pub fn checkers(friendly: &HalfBitBoard, enemy: &HalfBitBoard, c: Color) -> BoardMask {
+    let total = friendly.total | enemy.total;
+    Squares(friendly.kings)
+        .map(|king| enemy.attackers_to(c.opposite(), king, total))
+        .fold(0, |a, b| a | b)
+}
+
+/// Squares strictly between two squares on a line, or none if they are not on one
+pub fn between(a: Square, b: Square) -> BoardMask {
+    if rook_rays(a, 0) & b.bit() != 0 {
+        rook_rays(a, b.bit()) & rook_rays(b, a.bit())
+    } else if bishop_rays(a, 0) & b.bit() != 0 {
+        bishop_rays(a, b.bit()) & bishop_rays(b, a.bit())
+    } else {
+        0
+    }
+}
+
+/// Lone pieces of either side between `king` and a slider of `sliders`,
+/// with the squares from the king up to and including that slider
+fn blockers(
+    king: Square,
+    sliders: &HalfBitBoard,
+    total: BoardMask,
+) -> impl Iterator<Item = (Square, BoardMask)> {
+    let snipers = rook_rays(king, 0) & (sliders.rooks | sliders.queens)
+        | bishop_rays(king, 0) & (sliders.bishops | sliders.queens);
+
+    Squares(snipers).filter_map(move |sniper| {
+        let line = between(king, sniper);
+        let blocking = line & total;
+        (blocking.count_ones() == 1).then(|| {
+            let blocker = Squares(blocking).next().unwrap();
+            (blocker, line | sniper.bit())
+        })
+    })
+}
+
+/// King steps, and with a single checker its capture or an interposition
+/// by an unpinned piece
+pub fn legal_evasions(
+    friendly: &HalfBitBoard,
+    enemy: &HalfBitBoard,
+    metadata: Metadata,
+    checkers: BoardMask,
+    res: &mut Vec<ChessMove>,
+) {
+    let total = friendly.total | enemy.total;
+    let static_threats = enemy.attacks(metadata.to_move.opposite(), friendly.total);
+
+    for from in Squares(friendly.kings) {
+        for dst in Squares(KING_MOVES.at(from) & !static_threats & !friendly.total) {
+            encode_piece_move(
+                from.to(dst),
+                ChessPiece::King,
+                friendly,
+                enemy,
+                metadata,
+                res,
+            );
+        }
+    }
+
+    let (Some(king), Some(checker)) = (Squares(friendly.kings).next(), Squares(checkers).next())
+    else {
+        return;
+    };
+    if checkers.count_ones() > 1 {
+        return;
+    }
+
+    let target = checkers | between(king, checker);
+    let pinned = blockers(king, enemy, total)
+        .map(|(blocker, _)| blocker.bit())
+        .fold(0, |a, b| a | b);
+    let movable = friendly.total & !pinned;
+
+    for piece in [ChessPiece::Knight, ChessPiece::Bishop, ChessPiece::Rook, ChessPiece::Queen] {
+        for from in Squares(friendly.mask(piece) & movable) {
+            let attacks = match piece {
+                ChessPiece::Knight => KNIGHT_MOVES.at(from),
+                ChessPiece::Bishop => bishop_rays(from, total),
+                ChessPiece::Rook => rook_rays(from, total),
+                _ => queen_rays(from, total),
+            };
+
+            for dst in Squares(attacks & target) {
+                encode_piece_move(from.to(dst), piece, friendly, enemy, metadata, res);
+            }
+        }
+    }
+
+    let (move_fill, attack_fill): (fn(_, _) -> _, fn(_) -> _) = match metadata.to_move {
+        Color::White => (white_pawn_move_fill, white_pawn_attack_fill),
+        Color::Black => (black_pawn_move_fill, black_pawn_attack_fill),
+    };
+
+    // The en passant target is empty, so such captures are tried
+    // regardless and left to the legality check when encoding
+    let en_passant = one_bit(metadata.en_passant);
+
+    for from in Squares(friendly.pawns & movable) {
+        for dst in Squares(move_fill(from.bit(), !total) & target) {
+            encode_pawn_move(from.to(dst), None, friendly, enemy, metadata, res);
+        }
+
+        for dst in Squares(attack_fill(from.bit()) & (checkers | en_passant)) {
+            let cap = if dst.bit() & en_passant != 0 {
+                Square::new(dst.ix() - 8 * (metadata.to_move as i8))
+            } else {
+                Some(dst)
+            };
+            encode_pawn_move(from.to(dst), cap, friendly, enemy, metadata, res);
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use crate::{
+    bits::{
+        Bits, BoardMask,
+        board::{BitBoard, HalfBitBoard},
+    },
+    model::{
+        BoardRank, ChessPiece, Color, Square,
+        moves::{ChessMove, SpecialMove},
+    },
+};
+
+/// Piece values for exchanges, where the queen must be worth more than
+/// a rook and the king more than everything that can capture it
+pub const fn piece_value(p: ChessPiece) -> i16 {
+    match p {
+        ChessPiece::Pawn => ChessPiece::PAWN,
+        ChessPiece::Knight => ChessPiece::KNIGHT,
+        ChessPiece::Bishop => ChessPiece::BISHOP,
+        ChessPiece::Rook => ChessPiece::ROOK,
+        ChessPiece::Queen => 900,
+        ChessPiece::King => 10_000,
+    }
+}
+
+impl BitBoard {
+    /// Static exchange evaluation: the material balance for the moving side
+    /// after the best sequence of recaptures on the destination square
+    pub fn see(&self, mv: ChessMove) -> i16 {
+        let to = mv.pmv.to;
+        let color = mv.cpc.color();
+
+        let mut on_square = mv.cpc.piece();
+        let mut gain = [0i16; 32];
+
+        match mv.spc {
+            Some(SpecialMove::CastlingEastward | SpecialMove::CastlingWestward)
+            | Some(SpecialMove::Null) => return 0,
+            Some(SpecialMove::Promotion(p)) => {
+                on_square = p;
+                gain[0] = piece_value(p) - ChessPiece::PAWN;
+            }
+            None => {}
+        }
+
+        if let Some(p) = mv.cpc.capture() {
+            gain[0] += piece_value(p);
+        }
+
+        let mut occupied = (self.white.total | self.black.total) ^ mv.pmv.from.bit();
+        if let Some(sq) = mv.cap
+            && sq != to
+        {
+            occupied ^= sq.bit();
+        }
+
+        let mut side = color.opposite();
+        let mut depth = 0;
+
+        loop {
+            let (friendly, _) = self.active_passive(side);
+            let attackers = friendly.attackers_to(side, to, occupied);
+
+            let Some((piece, bit)) = least_valuable(friendly, attackers) else {
+                break;
+            };
+
+            if depth + 1 >= gain.len() {
+                break;
+            }
+
+            depth += 1;
+            gain[depth] = piece_value(on_square) - gain[depth - 1];
+
+            on_square = piece;
+            if piece == ChessPiece::Pawn && promotes(side, to) {
+                gain[depth] += piece_value(ChessPiece::Queen) - ChessPiece::PAWN;
+                on_square = ChessPiece::Queen;
+            }
+
+            occupied ^= bit;
+            side = side.opposite();
+        }
+
+        while depth > 0 {
+            gain[depth - 1] = -(-gain[depth - 1]).max(gain[depth]);
+            depth -= 1;
+        }
+
+        gain[0]
+    }
+}
+
+fn least_valuable(half: &HalfBitBoard, attackers: BoardMask) -> Option<(ChessPiece, BoardMask)> {
+    use ChessPiece::*;
+    for p in [Pawn, Knight, Bishop, Rook, Queen, King] {
+        if let Some(bit) = Bits(attackers & half.mask(p)).next() {
+            return Some((p, bit.get()));
+        }
+    }
+    None
+}
+
+fn promotes(c: Color, sq: Square) -> bool {
+    sq.file_rank().1 == c.opposite().rank()
+}
@@ -240,6 +240,30 @@ pub fn black_pawn_attack_fill(mask: u64) -> u64 {
     mask >> 9 & !BoardFile::H.mask() | mask >> 7 & !BoardFile::A.mask()
 }
 
+#[inline]
+pub fn north_fill(mask: u64) -> u64 {
+    let mask = mask | mask << 8;
+    let mask = mask | mask << 16;
+    mask | mask << 32
+}
+
+#[inline]
+pub fn south_fill(mask: u64) -> u64 {
+    let mask = mask | mask >> 8;
+    let mask = mask | mask >> 16;
+    mask | mask >> 32
+}
+
+#[inline]
+pub fn file_fill(mask: u64) -> u64 {
+    north_fill(mask) | south_fill(mask)
+}
+
+#[inline]
+pub fn east_west_fill(mask: u64) -> u64 {
+    (mask << 1 & !BoardFile::A.mask()) | (mask >> 1 & !BoardFile::H.mask())
+}
+
 #[test]
 fn fill_test() {
     let mut rng = pi_rng();
@@ -1,13 +1,17 @@
 pub mod attackers;
 pub mod attacks;
 pub mod board;
+pub mod checks;
+pub mod exchange;
 pub mod fills;
 pub mod jumps;
 pub mod movegen;
 pub mod moving;
+pub mod pawns;
 pub mod rays;
 pub mod slides;
 pub mod tests;
+pub mod unmoves;
 pub mod victory;
 
 use std::{num::NonZeroU64, u64};
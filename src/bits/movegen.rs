@@ -3,18 +3,19 @@ use crate::{
         BoardMask, Squares,
         attacks::superpiece_attacks,
         board::{BitBoard, HalfBitBoard},
+        checks::{checkers, legal_evasions},
         fills::{
             black_pawn_attack_fill, black_pawn_move_fill, white_pawn_attack_fill,
             white_pawn_move_fill,
         },
         jumps::{KING_MOVES, KNIGHT_MOVES},
         one_bit,
-        rays::{bishop_rays, rook_rays},
         slides::{
             obstruction_difference, simple_diagonal_attack, simple_omnidirectional_attack,
             simple_orthogonal_attack,
         },
     },
+    bits2::{bishop_rays, queen_rays, rook_rays},
     model::{
         BoardRank, ChessPiece, Color, Square,
         castling::CastlingDetail,
@@ -28,6 +29,63 @@ impl BitBoard {
         let (act, pas) = self.active_passive(self.metadata.to_move);
         legal_moves(act, pas, self.metadata, res);
     }
+
+    /// Captures and promotions only, or every legal move
+    /// when `evasions` is set and the side to move is in check
+    pub fn tactical_moves(&self, evasions: bool, res: &mut Vec<ChessMove>) {
+        let (act, pas) = self.active_passive(self.metadata.to_move);
+        if evasions && self.in_check() {
+            legal_moves(act, pas, self.metadata, res);
+        } else {
+            legal_tactical_moves(act, pas, self.metadata, res);
+        }
+    }
+
+    /// Every legal move `tactical_moves` leaves out
+    pub fn quiet_moves(&self, res: &mut Vec<ChessMove>) {
+        let (act, pas) = self.active_passive(self.metadata.to_move);
+        legal_quiet_moves(act, pas, self.metadata, res);
+    }
+
+    /// Pushes `mv` if it is a legal quiet move other than castling,
+    /// without generating the others
+    pub fn quiet_move(&self, mv: PseudoMove, res: &mut Vec<ChessMove>) {
+        let (friendly, enemy) = self.active_passive(self.metadata.to_move);
+        let total = friendly.total | enemy.total;
+        let Some(piece) = friendly.at(mv.from) else {
+            return;
+        };
+
+        let reach = match piece {
+            ChessPiece::Pawn => {
+                let move_fill = match self.metadata.to_move {
+                    Color::White => white_pawn_move_fill,
+                    Color::Black => black_pawn_move_fill,
+                };
+                move_fill(mv.from.bit(), !total) & !self.metadata.to_move.opposite().rank().mask()
+            }
+            ChessPiece::Knight => KNIGHT_MOVES.at(mv.from),
+            ChessPiece::Bishop => bishop_rays(mv.from, total),
+            ChessPiece::Rook => rook_rays(mv.from, total),
+            ChessPiece::Queen => queen_rays(mv.from, total),
+            ChessPiece::King => KING_MOVES.at(mv.from),
+        };
+
+        if reach & !total & mv.to.bit() == 0 {
+            return;
+        }
+
+        if piece == ChessPiece::Pawn {
+            encode_pawn_move(mv, None, friendly, enemy, self.metadata, res);
+        } else {
+            encode_piece_move(mv, piece, friendly, enemy, self.metadata, res);
+        }
+    }
+
+    pub fn in_check(&self) -> bool {
+        let (active, passive) = self.active_passive(self.metadata.to_move);
+        active.kings & passive.attacks(self.metadata.to_move.opposite(), active.total) != 0
+    }
 }
 
 pub fn legal_moves(
@@ -35,6 +93,21 @@ pub fn legal_moves(
     enemy: &HalfBitBoard,
     metadata: Metadata,
     res: &mut Vec<ChessMove>,
+) {
+    let checkers = checkers(friendly, enemy, metadata.to_move);
+    if checkers != 0 {
+        legal_evasions(friendly, enemy, metadata, checkers, res);
+    } else {
+        every_legal_move(friendly, enemy, metadata, res);
+    }
+}
+
+/// Tries every piece in turn, without the shortcut `legal_moves` takes in check
+pub fn every_legal_move(
+    friendly: &HalfBitBoard,
+    enemy: &HalfBitBoard,
+    metadata: Metadata,
+    res: &mut Vec<ChessMove>,
 ) {
     pawn_moves(friendly, enemy, metadata, res);
     pawn_captures(friendly, enemy, metadata, res);
@@ -45,6 +118,137 @@ pub fn legal_moves(
     king_moves(friendly, enemy, metadata, res);
 }
 
+pub fn legal_tactical_moves(
+    friendly: &HalfBitBoard,
+    enemy: &HalfBitBoard,
+    metadata: Metadata,
+    res: &mut Vec<ChessMove>,
+) {
+    pawn_promotions(friendly, enemy, metadata, res);
+    pawn_captures(friendly, enemy, metadata, res);
+    piece_captures(ChessPiece::Knight, friendly, enemy, metadata, res);
+    piece_captures(ChessPiece::Bishop, friendly, enemy, metadata, res);
+    piece_captures(ChessPiece::Rook, friendly, enemy, metadata, res);
+    piece_captures(ChessPiece::Queen, friendly, enemy, metadata, res);
+    piece_captures(ChessPiece::King, friendly, enemy, metadata, res);
+}
+
+/// Moves that neither capture nor promote, the complement of `legal_tactical_moves`
+pub fn legal_quiet_moves(
+    friendly: &HalfBitBoard,
+    enemy: &HalfBitBoard,
+    metadata: Metadata,
+    res: &mut Vec<ChessMove>,
+) {
+    pawn_pushes(friendly, enemy, metadata, res);
+    piece_quiets(ChessPiece::Knight, friendly, enemy, metadata, res);
+    piece_quiets(ChessPiece::Bishop, friendly, enemy, metadata, res);
+    piece_quiets(ChessPiece::Rook, friendly, enemy, metadata, res);
+    piece_quiets(ChessPiece::Queen, friendly, enemy, metadata, res);
+    piece_quiets(ChessPiece::King, friendly, enemy, metadata, res);
+
+    let static_threats = enemy.attacks(metadata.to_move.opposite(), friendly.total);
+    castling_moves(friendly, enemy, metadata, static_threats, res);
+}
+
+#[inline]
+pub fn piece_quiets(
+    piece: ChessPiece,
+    friendly: &HalfBitBoard,
+    enemy: &HalfBitBoard,
+    metadata: Metadata,
+    res: &mut Vec<ChessMove>,
+) {
+    let total = friendly.total | enemy.total;
+
+    for from in Squares(friendly.mask(piece)) {
+        let attacks = match piece {
+            ChessPiece::Knight => KNIGHT_MOVES.at(from),
+            ChessPiece::Bishop => bishop_rays(from, total),
+            ChessPiece::Rook => rook_rays(from, total),
+            ChessPiece::Queen => queen_rays(from, total),
+            ChessPiece::King => KING_MOVES.at(from),
+            ChessPiece::Pawn => continue,
+        };
+
+        for dst in Squares(attacks & !total) {
+            encode_piece_move(from.to(dst), piece, friendly, enemy, metadata, res);
+        }
+    }
+}
+
+/// Pawn pushes short of the last rank
+#[inline]
+pub fn pawn_pushes(
+    friendly: &HalfBitBoard,
+    enemy: &HalfBitBoard,
+    metadata: Metadata,
+    res: &mut Vec<ChessMove>,
+) {
+    let move_fill = match metadata.to_move {
+        Color::White => white_pawn_move_fill,
+        Color::Black => black_pawn_move_fill,
+    };
+
+    let empty = !(friendly.total | enemy.total);
+    let last_rank = metadata.to_move.opposite().rank().mask();
+
+    for from in Squares(friendly.pawns) {
+        for dst in Squares(move_fill(from.bit(), empty) & !last_rank) {
+            encode_pawn_move(from.to(dst), None, friendly, enemy, metadata, res);
+        }
+    }
+}
+
+#[inline]
+pub fn piece_captures(
+    piece: ChessPiece,
+    friendly: &HalfBitBoard,
+    enemy: &HalfBitBoard,
+    metadata: Metadata,
+    res: &mut Vec<ChessMove>,
+) {
+    let total = friendly.total | enemy.total;
+
+    for from in Squares(friendly.mask(piece)) {
+        let attacks = match piece {
+            ChessPiece::Knight => KNIGHT_MOVES.at(from),
+            ChessPiece::Bishop => bishop_rays(from, total),
+            ChessPiece::Rook => rook_rays(from, total),
+            ChessPiece::Queen => queen_rays(from, total),
+            ChessPiece::King => KING_MOVES.at(from),
+            ChessPiece::Pawn => continue,
+        };
+
+        for dst in Squares(attacks & enemy.total) {
+            encode_piece_move(from.to(dst), piece, friendly, enemy, metadata, res);
+        }
+    }
+}
+
+#[inline]
+pub fn pawn_promotions(
+    friendly: &HalfBitBoard,
+    enemy: &HalfBitBoard,
+    metadata: Metadata,
+    res: &mut Vec<ChessMove>,
+) {
+    let move_fill = match metadata.to_move {
+        Color::White => white_pawn_move_fill,
+        Color::Black => black_pawn_move_fill,
+    };
+
+    let empty = !(friendly.total | enemy.total);
+    let last_rank = metadata.to_move.opposite().rank().mask();
+    let seventh_rank = metadata.to_move.opposite().pawn_rank().mask();
+
+    for from in Squares(friendly.pawns & seventh_rank) {
+        for dst in Squares(move_fill(from.bit(), empty) & last_rank) {
+            encode_pawn_move(from.to(dst), None, friendly, enemy, metadata, res);
+        }
+    }
+}
+
 #[inline]
 pub fn knight_moves(
     friendly: &HalfBitBoard,
@@ -105,7 +309,7 @@ pub fn bishop_moves(
     res: &mut Vec<ChessMove>,
 ) {
     for from in Squares(friendly.bishops) {
-        let attacks = simple_diagonal_attack(from, friendly.total | enemy.total);
+        let attacks = bishop_rays(from, friendly.total | enemy.total);
 
         let mask = attacks & !friendly.total;
 
@@ -130,7 +334,7 @@ pub fn queen_moves(
     res: &mut Vec<ChessMove>,
 ) {
     for from in Squares(friendly.queens) {
-        let attacks = simple_omnidirectional_attack(from, friendly.total | enemy.total);
+        let attacks = queen_rays(from, friendly.total | enemy.total);
 
         let mask = attacks & !friendly.total;
 
@@ -228,19 +432,26 @@ pub fn king_moves(
         }
     }
 
+    castling_moves(friendly, enemy, metadata, static_threats, res);
+}
+
+#[inline]
+pub fn castling_moves(
+    friendly: &HalfBitBoard,
+    enemy: &HalfBitBoard,
+    metadata: Metadata,
+    static_threats: BoardMask,
+    res: &mut Vec<ChessMove>,
+) {
+    let total = friendly.total | enemy.total;
+
     if metadata.castling_rights.westward(metadata.to_move) {
         encode_castling_move(
             metadata.castling_details.westward,
             SpecialMove::CastlingWestward,
             metadata,
             static_threats,
-            ({
-                let this = &friendly;
-                this.total
-            }) | {
-                let this = &enemy;
-                this.total
-            },
+            total,
             res,
         );
     }
@@ -251,13 +462,7 @@ pub fn king_moves(
             SpecialMove::CastlingEastward,
             metadata,
             static_threats,
-            ({
-                let this = &friendly;
-                this.total
-            }) | {
-                let this = &enemy;
-                this.total
-            },
+            total,
             res,
         );
     }
@@ -0,0 +1,144 @@
+use crate::{
+    bits::{
+        BoardMask,
+        board::{BitBoard, HalfBitBoard},
+        fills::{
+            black_pawn_attack_fill, east_west_fill, file_fill, north_fill, south_fill,
+            white_pawn_attack_fill,
+        },
+    },
+    model::Color,
+};
+
+/// Structural features of one side's pawns; files are given as whole-file masks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PawnStructure {
+    pub passed: BoardMask,
+    pub isolated: BoardMask,
+    pub doubled: BoardMask,
+    pub backward: BoardMask,
+    pub connected: BoardMask,
+    pub islands: u32,
+    pub open_files: BoardMask,
+    pub half_open_files: BoardMask,
+}
+
+/// Squares in front of `mask`, not including it, as seen by `c`
+pub fn front_span(mask: BoardMask, c: Color) -> BoardMask {
+    match c {
+        Color::White => north_fill(mask << 8),
+        Color::Black => south_fill(mask >> 8),
+    }
+}
+
+/// Squares behind `mask`, not including it, as seen by `c`
+pub fn rear_span(mask: BoardMask, c: Color) -> BoardMask {
+    front_span(mask, c.opposite())
+}
+
+fn pawn_attack_fill(mask: BoardMask, c: Color) -> BoardMask {
+    match c {
+        Color::White => white_pawn_attack_fill(mask),
+        Color::Black => black_pawn_attack_fill(mask),
+    }
+}
+
+impl HalfBitBoard {
+    pub fn isolated_pawns(&self) -> BoardMask {
+        self.pawns & !east_west_fill(file_fill(self.pawns))
+    }
+
+    /// Every pawn with a friendly pawn ahead of it on the same file
+    pub fn doubled_pawns(&self, c: Color) -> BoardMask {
+        self.pawns & rear_span(self.pawns, c)
+    }
+
+    /// Pawns defended by a friendly pawn or standing next to one
+    pub fn connected_pawns(&self, c: Color) -> BoardMask {
+        self.pawns & (pawn_attack_fill(self.pawns, c) | east_west_fill(self.pawns))
+    }
+
+    /// The pawns of each group of adjacent files holding pawns, from the a-file
+    pub fn pawn_islands(&self) -> Vec<BoardMask> {
+        let mut res = vec![];
+        let mut files = self.pawn_files();
+
+        while files != 0 {
+            let start = files.trailing_zeros();
+            let len = (files >> start).trailing_ones();
+            let island = (((1u16 << len) - 1) << start) as u8;
+            res.push(self.pawns & file_fill(island as BoardMask));
+            files &= !island;
+        }
+
+        res
+    }
+
+    pub fn pawn_island_count(&self) -> u32 {
+        let files = self.pawn_files();
+        (files & !(files << 1)).count_ones()
+    }
+
+    /// One bit per file holding pawns, a-file lowest
+    fn pawn_files(&self) -> u8 {
+        file_fill(self.pawns) as u8
+    }
+
+    /// Files without pawns of this side
+    pub fn pawnless_files(&self) -> BoardMask {
+        !file_fill(self.pawns)
+    }
+}
+
+impl BitBoard {
+    /// Pawns with no enemy pawns ahead of them on their own or adjacent files
+    pub fn passed_pawns(&self, c: Color) -> BoardMask {
+        let (friendly, enemy) = self.active_passive(c);
+        let enemy_span = enemy.pawns | front_span(enemy.pawns, c.opposite());
+        friendly.pawns & !(enemy_span | east_west_fill(enemy_span))
+    }
+
+    /// Pawns that can no longer be defended by friendly pawns and
+    /// whose advance is held by an enemy pawn
+    pub fn backward_pawns(&self, c: Color) -> BoardMask {
+        let (friendly, enemy) = self.active_passive(c);
+        let stops = match c {
+            Color::White => friendly.pawns << 8,
+            Color::Black => friendly.pawns >> 8,
+        };
+        let defensible = {
+            let attacks = pawn_attack_fill(friendly.pawns, c);
+            attacks | front_span(attacks, c)
+        };
+        let held = stops & pawn_attack_fill(enemy.pawns, c.opposite()) & !defensible;
+
+        match c {
+            Color::White => held >> 8,
+            Color::Black => held << 8,
+        }
+    }
+
+    pub fn open_files(&self) -> BoardMask {
+        self.white.pawnless_files() & self.black.pawnless_files()
+    }
+
+    /// Files without friendly pawns that still hold enemy pawns
+    pub fn half_open_files(&self, c: Color) -> BoardMask {
+        let (friendly, enemy) = self.active_passive(c);
+        friendly.pawnless_files() & !enemy.pawnless_files()
+    }
+
+    pub fn pawn_structure(&self, c: Color) -> PawnStructure {
+        let (friendly, _) = self.active_passive(c);
+        PawnStructure {
+            passed: self.passed_pawns(c),
+            isolated: friendly.isolated_pawns(),
+            doubled: friendly.doubled_pawns(c),
+            backward: self.backward_pawns(c),
+            connected: friendly.connected_pawns(c),
+            islands: friendly.pawn_island_count(),
+            open_files: self.open_files(),
+            half_open_files: self.half_open_files(c),
+        }
+    }
+}
@@ -1,20 +1,26 @@
+use rand::seq::IndexedRandom;
+
 use crate::{
     arrays::ArrayBoard,
     bits::{
         BoardMask, Squares,
         attacks::{bishop_attacks, knight_attacks, rook_attacks},
         board::{BitBoard, HalfBitBoard},
+        exchange::piece_value,
         jumps::KNIGHT_MOVES,
         mask,
-        movegen::{legal_moves, pawn_moves},
-        one_bit, show_mask, slides,
+        movegen::{every_legal_move, legal_moves, pawn_moves},
+        one_bit,
+        pawns::PawnStructure,
+        show_mask, slides,
+        unmoves::UnMove,
     },
-    fuzzing::stockfish_perft,
+    fuzzing::{pi_rng, stockfish_perft},
     model::{
-        Color, ColoredChessPiece, Square,
+        BoardFile, Color, ColoredChessPiece, Square,
         castling::{CLASSIC_CASTLING, CastlingRights},
         metadata::Metadata,
-        moves::{ChessMove, PseudoMove},
+        moves::{ChessMove, PseudoMove, SpecialMove},
     },
     notation::{
         algebraic,
@@ -384,3 +390,495 @@ fn en_passant_pawn_capture() {
         println!("{:?}", mv)
     }
 }
+
+#[cfg(test)]
+fn see_of(fen: &str, mv: (PseudoMove, Option<ChessPiece>)) -> i16 {
+    let board = parse_fen(fen).unwrap();
+    let mut moves = vec![];
+    board.moves(&mut moves);
+    let mv = moves
+        .into_iter()
+        .find(|m| m.simplify() == mv)
+        .expect("move not legal");
+    board.see(mv)
+}
+
+#[test]
+fn static_exchange_evaluation() {
+    assert_eq!(
+        see_of(
+            "1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1",
+            Square::e1.to(Square::e5).p()
+        ),
+        100
+    );
+
+    assert_eq!(
+        see_of(
+            "1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1",
+            Square::d3.to(Square::e5).p()
+        ),
+        ChessPiece::PAWN - ChessPiece::KNIGHT
+    );
+
+    assert_eq!(
+        see_of(
+            "4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1",
+            Square::e4.to(Square::d5).p()
+        ),
+        100
+    );
+
+    assert_eq!(
+        see_of(
+            "4k3/8/2p5/3p4/8/8/8/3QK3 w - - 0 1",
+            Square::d1.to(Square::d5).p()
+        ),
+        ChessPiece::PAWN - piece_value(ChessPiece::Queen)
+    );
+
+    assert_eq!(
+        see_of(
+            "4k3/8/2p5/3p4/4Q3/8/8/4K3 b - - 0 1",
+            Square::d5.to(Square::e4).p()
+        ),
+        piece_value(ChessPiece::Queen)
+    );
+}
+
+#[test]
+fn static_exchange_special_moves() {
+    assert_eq!(
+        see_of(
+            "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1",
+            Square::e5.to(Square::d6).p()
+        ),
+        100
+    );
+
+    assert_eq!(
+        see_of(
+            "4k3/2b5/8/3pP3/8/8/8/4K3 w - d6 0 1",
+            Square::e5.to(Square::d6).p()
+        ),
+        0
+    );
+
+    assert_eq!(
+        see_of(
+            "7k/P7/8/8/8/8/8/4K3 w - - 0 1",
+            Square::a7.to(Square::a8).q()
+        ),
+        piece_value(ChessPiece::Queen) - ChessPiece::PAWN
+    );
+
+    assert_eq!(
+        see_of(
+            "r6k/1P6/8/8/8/8/8/4K3 w - - 0 1",
+            Square::b7.to(Square::b8).q()
+        ),
+        -ChessPiece::PAWN
+    );
+
+    assert_eq!(
+        see_of(
+            "1r5k/P7/8/8/8/8/8/R3K3 w - - 0 1",
+            Square::a7.to(Square::b8).q()
+        ),
+        ChessPiece::ROOK + piece_value(ChessPiece::Queen) - ChessPiece::PAWN
+    );
+}
+
+#[test]
+fn tactical_moves_match_filtered_moves() {
+    let mut rng = pi_rng();
+    let mut moves = vec![];
+    let mut tactical = vec![];
+
+    for _ in 0..200 {
+        let mut board = BitBoard::startpos();
+
+        for _ in 0..80 {
+            moves.clear();
+            tactical.clear();
+            board.moves(&mut moves);
+            board.tactical_moves(false, &mut tactical);
+
+            let mut expected = moves
+                .iter()
+                .filter(|m| m.cap.is_some() || matches!(m.spc, Some(SpecialMove::Promotion(_))))
+                .map(|m| m.simplify())
+                .collect::<Vec<_>>();
+            let mut found = tactical.iter().map(|m| m.simplify()).collect::<Vec<_>>();
+            expected.sort();
+            found.sort();
+
+            assert_eq!(expected, found, "{}", render_fen(&board));
+
+            let Some(mv) = moves.choose(&mut rng) else {
+                break;
+            };
+            board.apply(*mv);
+        }
+    }
+}
+
+#[test]
+fn quiet_move_matches_quiet_moves() {
+    let mut rng = pi_rng();
+    let mut moves = vec![];
+    let mut quiet = vec![];
+
+    for _ in 0..20 {
+        let mut board = BitBoard::startpos();
+
+        for _ in 0..80 {
+            moves.clear();
+            board.quiet_moves(&mut moves);
+            moves.retain(|m| {
+                !matches!(
+                    m.spc,
+                    Some(SpecialMove::CastlingEastward | SpecialMove::CastlingWestward)
+                )
+            });
+
+            quiet.clear();
+            for from in Squares(!0) {
+                for to in Squares(!0) {
+                    board.quiet_move(from.to(to), &mut quiet);
+                }
+            }
+            let mut expected = moves.iter().map(|m| m.simplify()).collect::<Vec<_>>();
+            let mut found = quiet.iter().map(|m| m.simplify()).collect::<Vec<_>>();
+            expected.sort();
+            found.sort();
+            assert_eq!(expected, found, "{}", render_fen(&board));
+
+            moves.clear();
+            board.moves(&mut moves);
+            let Some(mv) = moves.choose(&mut rng) else {
+                break;
+            };
+            board.apply(*mv);
+        }
+    }
+}
+
+#[test]
+fn pawn_structure() {
+    let board = parse_fen("4k3/8/1p1p4/4p2P/2P1P3/2P5/P7/4K3 w - - 0 1").unwrap();
+    let open = BoardFile::F.mask() | BoardFile::G.mask();
+
+    assert_eq!(
+        board.pawn_structure(Color::White),
+        PawnStructure {
+            passed: Square::h5.bit(),
+            isolated: board.white.pawns,
+            doubled: Square::c3.bit(),
+            backward: Square::c4.bit() | Square::e4.bit(),
+            connected: 0,
+            islands: 4,
+            open_files: open,
+            half_open_files: BoardFile::B.mask() | BoardFile::D.mask(),
+        }
+    );
+
+    assert_eq!(
+        board.pawn_structure(Color::Black),
+        PawnStructure {
+            passed: 0,
+            isolated: Square::b6.bit(),
+            doubled: 0,
+            backward: Square::b6.bit() | Square::d6.bit(),
+            connected: Square::e5.bit(),
+            islands: 2,
+            open_files: open,
+            half_open_files: BoardFile::A.mask() | BoardFile::C.mask() | BoardFile::H.mask(),
+        }
+    );
+
+    assert_eq!(
+        board.white.pawn_islands(),
+        vec![
+            Square::a2.bit(),
+            Square::c3.bit() | Square::c4.bit(),
+            Square::e4.bit(),
+            Square::h5.bit()
+        ]
+    );
+    assert_eq!(
+        board.black.pawn_islands(),
+        vec![Square::b6.bit(), Square::d6.bit() | Square::e5.bit()]
+    );
+}
+
+#[test]
+fn quiet_unmoves_retract() {
+    let mut rng = pi_rng();
+    let mut moves = vec![];
+    let mut unmoves = vec![];
+
+    for _ in 0..100 {
+        let mut board = BitBoard::startpos();
+
+        for _ in 0..80 {
+            moves.clear();
+            board.moves(&mut moves);
+            let Some(mv) = moves.choose(&mut rng).copied() else {
+                break;
+            };
+            let before = board.clone();
+            board.apply(mv);
+
+            unmoves.clear();
+            board.quiet_unmoves(&mut unmoves);
+
+            if mv.cap.is_none() && mv.spc.is_none() {
+                assert!(
+                    unmoves.iter().any(|um| um.pmv == mv.pmv),
+                    "{} after {}",
+                    render_fen(&board),
+                    render_fen(&before)
+                );
+            }
+
+            for um in &unmoves {
+                let mut prev = board.retract(*um);
+                moves.clear();
+                prev.moves(&mut moves);
+                let forward = *moves
+                    .iter()
+                    .find(|m| m.pmv == um.pmv && m.spc.is_none())
+                    .unwrap_or_else(|| panic!("{:?} from {}", um, render_fen(&prev)));
+                assert_eq!(forward.cpc.piece(), um.piece);
+                assert_eq!(forward.cap, None);
+
+                prev.apply(forward);
+                assert_eq!(prev.white, board.white);
+                assert_eq!(prev.black, board.black);
+                assert_eq!(prev.metadata.to_move, board.metadata.to_move);
+            }
+        }
+    }
+}
+
+#[test]
+fn unmoves_roundtrip() {
+    let mut rng = pi_rng();
+    let mut moves = vec![];
+
+    for _ in 0..60 {
+        let mut board = BitBoard::startpos();
+
+        for _ in 0..120 {
+            moves.clear();
+            board.moves(&mut moves);
+            let Some(mv) = moves.choose(&mut rng).copied() else {
+                break;
+            };
+            board.apply(mv);
+
+            let predecessors = board.predecessors();
+            assert!(
+                predecessors.iter().any(|(um, _)| *um == UnMove::of(mv)),
+                "{:?} into {}",
+                mv,
+                render_fen(&board)
+            );
+
+            for (um, mut prev) in predecessors {
+                moves.clear();
+                prev.moves(&mut moves);
+                let forward = *moves
+                    .iter()
+                    .find(|m| UnMove::of(**m) == um)
+                    .unwrap_or_else(|| panic!("{:?} from {}", um, render_fen(&prev)));
+
+                prev.apply(forward);
+                assert_eq!(prev.white, board.white);
+                assert_eq!(prev.black, board.black);
+                assert_eq!(prev.metadata.to_move, board.metadata.to_move);
+                if board.metadata.en_passant.is_some() {
+                    assert_eq!(prev.metadata.en_passant, board.metadata.en_passant);
+                }
+                assert_eq!(
+                    prev.metadata.castling_rights,
+                    board.metadata.castling_rights
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn unmoves_special() {
+    let unmoves = |fen: &str| {
+        let mut res = vec![];
+        parse_fen(fen).unwrap().unmoves(&mut res);
+        res
+    };
+    let sq = |s: &str| Square::parse(s).unwrap();
+
+    let double = unmoves("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2");
+    assert_eq!(double.len(), 1);
+    assert_eq!(double[0].pmv, sq("d7").to(sq("d5")));
+
+    let board = parse_fen("4k3/8/3P4/8/8/8/8/4K3 b - - 0 1").unwrap();
+    let (um, prev) = board
+        .predecessors()
+        .into_iter()
+        .find(|(um, _)| um.en_passant)
+        .unwrap();
+    assert_eq!(um.uncapture, Some(ChessPiece::Pawn));
+    assert_eq!(prev.metadata.en_passant, Some(sq("d6")));
+    assert_eq!(prev.black.pawns, sq("d5").bit());
+
+    let promoted = unmoves("4k2Q/8/8/8/8/8/8/4K3 b - - 0 1");
+    assert!(promoted.contains(&UnMove {
+        piece: ChessPiece::Pawn,
+        pmv: sq("h7").to(sq("h8")),
+        uncapture: None,
+        spc: Some(SpecialMove::Promotion(ChessPiece::Queen)),
+        en_passant: false,
+    }));
+    assert!(
+        promoted
+            .iter()
+            .any(|um| um.pmv == sq("g7").to(sq("h8")) && um.uncapture == Some(ChessPiece::Rook))
+    );
+
+    let board = parse_fen("4k3/8/8/8/8/8/8/5RK1 b - - 0 1").unwrap();
+    let (_, prev) = board
+        .predecessors()
+        .into_iter()
+        .find(|(um, _)| um.spc == Some(SpecialMove::CastlingEastward))
+        .unwrap();
+    assert_eq!(prev.white.kings, sq("e1").bit());
+    assert_eq!(prev.white.rooks, sq("h1").bit());
+    assert!(prev.metadata.castling_rights.eastward(Color::White));
+    assert!(!prev.metadata.castling_rights.westward(Color::White));
+
+    let attacked = "4k3/8/8/8/8/8/8/r4RK1 b - - 0 1";
+    assert!(
+        !unmoves(attacked)
+            .iter()
+            .any(|um| um.spc == Some(SpecialMove::CastlingEastward))
+    );
+
+    assert!(unmoves("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1").is_empty());
+    assert!(unmoves("4k3/8/8/8/8/8/8/4RK2 w - - 0 1").is_empty());
+
+    let board = parse_fen("4k3/8/8/8/8/8/4P3/4K2R b - - 5 20").unwrap();
+    let predecessors = board.predecessors();
+    assert!(!predecessors.is_empty());
+    for (um, prev) in predecessors {
+        assert!(!um.irreversible());
+        assert_eq!(prev.metadata.halfmove_clock, 4);
+    }
+}
+
+#[test]
+fn check_info_pins_and_checks() {
+    let sq = |s: &str| Square::parse(s).unwrap();
+    let bits = |s: &[&str]| s.iter().map(|s| sq(s).bit()).fold(0, |a, b| a | b);
+
+    let info = parse_fen("4r1k1/8/8/8/1b6/8/3N4/R3K3 w Q - 0 1")
+        .unwrap()
+        .check_info();
+    assert_eq!(info.checkers, bits(&["e8"]));
+    assert_eq!(info.pinned, bits(&["d2"]));
+    assert_eq!(info.pin_rays.at(sq("d2")), bits(&["d2", "c3", "b4"]));
+    assert_eq!(info.pin_rays.at(sq("a1")), 0);
+    assert_eq!(info.discoverers, 0);
+
+    let info = parse_fen("6k1/8/4N3/8/2B5/8/8/4K3 w - - 0 1")
+        .unwrap()
+        .check_info();
+    assert_eq!(info.checkers, 0);
+    assert_eq!(info.pinned, 0);
+    assert_eq!(info.discoverers, bits(&["e6"]));
+    assert_eq!(info.check_squares(ChessPiece::Pawn), bits(&["f7", "h7"]));
+    assert_eq!(
+        info.check_squares(ChessPiece::Knight),
+        bits(&["e7", "f6", "h6"])
+    );
+    assert_eq!(
+        info.check_squares(ChessPiece::Bishop),
+        bits(&["f7", "e6", "h7"])
+    );
+    assert_eq!(info.check_squares(ChessPiece::King), 0);
+}
+
+#[test]
+fn evasions_match_legal_moves() {
+    let mut rng = pi_rng();
+    let mut moves = vec![];
+    let mut checks = 0;
+
+    for _ in 0..200 {
+        let mut board = BitBoard::startpos();
+
+        for _ in 0..120 {
+            let mut evasions = vec![];
+            board.evasions(&mut evasions);
+
+            let (act, pas) = board.active_passive(board.metadata.to_move);
+            moves.clear();
+            every_legal_move(act, pas, board.metadata, &mut moves);
+
+            if board.in_check() {
+                checks += 1;
+                let key = |mv: &ChessMove| mv.simplify();
+                evasions.sort_by_key(key);
+                moves.sort_by_key(key);
+                assert_eq!(evasions, moves, "{}", render_fen(&board));
+            } else {
+                assert!(evasions.is_empty());
+            }
+
+            let Some(mv) = moves.choose(&mut rng).copied() else {
+                break;
+            };
+            board.apply(mv);
+        }
+    }
+
+    assert!(checks > 100, "only {checks} positions in check");
+}
+
+#[test]
+fn evasions_in_check() {
+    let fens = [
+        // Double check
+        "4k3/8/8/8/8/5n2/8/r3K3 w - - 0 1",
+        // En passant takes the checking pawn
+        "8/8/8/2k5/3Pp3/8/8/4K3 b - d3 0 1",
+        // The knight could block but is pinned, and castling is out
+        "4k3/8/8/8/1b6/8/3N4/R3K2r w Q - 0 1",
+        // Capturing the checker while promoting
+        "3r2k1/4P3/8/8/8/8/8/3K4 w - - 0 1",
+        // Blocking by promoting
+        "K6r/1P6/8/8/8/8/8/7k w - - 0 1",
+        // The king cannot step back along the rook's line
+        "4k3/8/8/8/8/8/8/4R2K b - - 0 1",
+        // Checkmate
+        "R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1",
+    ];
+
+    for fen in fens {
+        let board = parse_fen(fen).unwrap();
+        assert!(board.in_check(), "{fen}");
+
+        let mut evasions = vec![];
+        board.evasions(&mut evasions);
+        let (act, pas) = board.active_passive(board.metadata.to_move);
+        let mut moves = vec![];
+        every_legal_move(act, pas, board.metadata, &mut moves);
+
+        let key = |mv: &ChessMove| mv.simplify();
+        evasions.sort_by_key(key);
+        moves.sort_by_key(key);
+        assert_eq!(evasions, moves, "{fen}");
+        assert_eq!(moves.is_empty(), fen == fens[fens.len() - 1], "{fen}");
+    }
+}
@@ -0,0 +1,418 @@
+use strum::VariantArray;
+
+use crate::{
+    bits::{
+        BoardMask, Squares,
+        attacks::pawn_attacks,
+        board::{BitBoard, HalfBitBoard},
+        jumps::{KING_MOVES, KNIGHT_MOVES},
+        rays::{bishop_rays, queen_rays, rook_rays},
+    },
+    model::{
+        BoardFile, BoardRank, ChessPiece, Color, Square,
+        castling::{CastlingDetails, CastlingMove, CastlingRights},
+        moves::{ChessMove, PseudoMove, SpecialMove},
+    },
+    zobrist::ZOBRIST,
+};
+
+/// A move by the side not to move that could have led to the
+/// current position, given in the direction it was played
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct UnMove {
+    pub piece: ChessPiece,
+    pub pmv: PseudoMove,
+    /// The piece put back, on `pmv.to` unless the capture was en passant
+    pub uncapture: Option<ChessPiece>,
+    pub spc: Option<SpecialMove>,
+    pub en_passant: bool,
+}
+
+impl UnMove {
+    const fn quiet(piece: ChessPiece, pmv: PseudoMove) -> Self {
+        Self {
+            piece,
+            pmv,
+            uncapture: None,
+            spc: None,
+            en_passant: false,
+        }
+    }
+
+    /// The un-move taking back `mv`
+    pub const fn of(mv: ChessMove) -> Self {
+        Self {
+            piece: mv.cpc.piece(),
+            pmv: mv.pmv,
+            uncapture: mv.cpc.capture(),
+            spc: mv.spc,
+            en_passant: match mv.cap {
+                Some(sq) => sq.ix() != mv.pmv.to.ix(),
+                None => false,
+            },
+        }
+    }
+
+    pub const fn irreversible(self) -> bool {
+        self.piece as i8 == ChessPiece::Pawn as i8 || self.uncapture.is_some()
+    }
+}
+
+const UNCAPTURES: [ChessPiece; 5] = [
+    ChessPiece::Pawn,
+    ChessPiece::Knight,
+    ChessPiece::Bishop,
+    ChessPiece::Rook,
+    ChessPiece::Queen,
+];
+
+impl BitBoard {
+    /// Un-moves that neither uncapture nor unpromote, from legal predecessors
+    /// without castling rights or en passant
+    pub fn quiet_unmoves(&self, res: &mut Vec<UnMove>) {
+        let mover = self.metadata.to_move.opposite();
+        let (moved, other) = self.active_passive(mover);
+        let occupied = moved.total | other.total;
+
+        for &p in ChessPiece::VARIANTS {
+            for to in Squares(moved.mask(p)) {
+                for from in Squares(unmove_origins(p, mover, to, occupied)) {
+                    let pmv = from.to(to);
+                    if legal_predecessor(moved, other, p, pmv, mover) {
+                        res.push(UnMove::quiet(p, pmv));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Every un-move from a legal predecessor. A zero halfmove clock
+    /// and a missing en passant square are taken as unknown
+    pub fn unmoves(&self, res: &mut Vec<UnMove>) {
+        res.extend(self.predecessors().into_iter().map(|(um, _)| um));
+    }
+
+    /// The legal positions one move back, each with the un-move leading here
+    pub fn predecessors(&self) -> Vec<(UnMove, BitBoard)> {
+        let mover = self.metadata.to_move.opposite();
+        let (moved, other) = self.active_passive(mover);
+        if other.attacks(mover.opposite(), moved.total) & moved.kings != 0 {
+            return vec![];
+        }
+
+        let mut candidates = vec![];
+        self.unmove_candidates(mover, &mut candidates);
+
+        candidates
+            .into_iter()
+            .filter(|um| self.consistent_unmove(*um))
+            .map(|um| (um, self.retract(um)))
+            .filter(|(um, prev)| legal_retraction(prev, *um))
+            .collect()
+    }
+
+    /// The position before `um` was played, with the fewest castling rights
+    /// that allow it and en passant only where `um` captured en passant
+    pub fn retract(&self, um: UnMove) -> BitBoard {
+        let mut res = self.clone();
+        let mover = self.metadata.to_move.opposite();
+        let details = self.metadata.castling_details;
+        let (half, other) = match mover {
+            Color::White => (&mut res.white, &mut res.black),
+            Color::Black => (&mut res.black, &mut res.white),
+        };
+
+        let mut rights = self.metadata.castling_rights;
+        match um.spc {
+            Some(SpecialMove::Promotion(p)) => {
+                *half.piece(p) ^= um.pmv.to.bit();
+                half.pawns ^= um.pmv.from.bit();
+                half.total ^= um.pmv.bits();
+            }
+            Some(spc @ (SpecialMove::CastlingWestward | SpecialMove::CastlingEastward)) => {
+                let cmv = castling_move(spc, mover, details);
+                let (king, rook) = (cmv.king_move, cmv.rook_move);
+                half.kings ^= king.to.bit() ^ king.from.bit();
+                half.rooks ^= rook.to.bit() ^ rook.from.bit();
+                half.total = half.total & !(king.to.bit() | rook.to.bit())
+                    | king.from.bit()
+                    | rook.from.bit();
+                rights = rights.union(castling_right(spc, mover));
+            }
+            _ => {
+                *half.piece(um.piece) ^= um.pmv.bits();
+                half.total ^= um.pmv.bits();
+            }
+        }
+
+        if let Some(q) = um.uncapture {
+            let sq = if um.en_passant {
+                behind(um.pmv.to.bit(), mover)
+            } else {
+                um.pmv.to.bit()
+            };
+            *other.piece(q) |= sq;
+            other.total |= sq;
+        }
+
+        res.metadata.to_move = mover;
+        res.metadata.en_passant = if um.en_passant { Some(um.pmv.to) } else { None };
+        res.metadata.castling_rights = rights;
+        res.metadata.halfmove_clock = if um.irreversible() {
+            0
+        } else {
+            self.metadata.halfmove_clock.saturating_sub(1)
+        };
+        if mover == Color::Black {
+            res.metadata.turn = res.metadata.turn.saturating_sub(1).max(1);
+        }
+        res.metadata.hash = ZOBRIST.hash(&res);
+        res
+    }
+
+    /// Un-moves by `mover` that fit the pieces on the board, before checking
+    /// the metadata or the legality of the predecessor
+    fn unmove_candidates(&self, mover: Color, res: &mut Vec<UnMove>) {
+        let (moved, other) = self.active_passive(mover);
+        let occupied = moved.total | other.total;
+        let last = mover.opposite().rank();
+
+        let uncaptures = |to: Square| {
+            UNCAPTURES
+                .into_iter()
+                .filter(move |q| *q != ChessPiece::Pawn || !on_edge_rank(to))
+        };
+
+        for &p in ChessPiece::VARIANTS {
+            for to in Squares(moved.mask(p)) {
+                for from in Squares(unmove_origins(p, mover, to, occupied)) {
+                    let um = UnMove::quiet(p, from.to(to));
+                    res.push(um);
+                    if p != ChessPiece::Pawn {
+                        res.extend(uncaptures(to).map(|q| UnMove {
+                            uncapture: Some(q),
+                            ..um
+                        }));
+                    }
+                }
+
+                let diagonal = pawn_attacks(to.bit(), mover.opposite()) & !occupied;
+                if p == ChessPiece::Pawn {
+                    for from in Squares(diagonal & !start_rank_behind(mover)) {
+                        let um = UnMove::quiet(p, from.to(to));
+                        res.extend(uncaptures(to).map(|q| UnMove {
+                            uncapture: Some(q),
+                            ..um
+                        }));
+
+                        let captured = behind(to.bit(), mover);
+                        let double_from = ahead(to.bit(), mover);
+                        if to.file_rank().1 == en_passant_rank(mover)
+                            && (captured | double_from) & occupied == 0
+                        {
+                            res.push(UnMove {
+                                uncapture: Some(ChessPiece::Pawn),
+                                en_passant: true,
+                                ..um
+                            });
+                        }
+                    }
+                } else if p != ChessPiece::King && to.file_rank().1 == last {
+                    let unpromote = |from: Square| UnMove {
+                        spc: Some(SpecialMove::Promotion(p)),
+                        ..UnMove::quiet(ChessPiece::Pawn, from.to(to))
+                    };
+
+                    for from in Squares(behind(to.bit(), mover) & !occupied) {
+                        res.push(unpromote(from));
+                    }
+                    for from in Squares(diagonal) {
+                        let um = unpromote(from);
+                        res.extend(uncaptures(to).map(|q| UnMove {
+                            uncapture: Some(q),
+                            ..um
+                        }));
+                    }
+                }
+            }
+        }
+
+        if self.metadata.castling_rights.get(mover) != 0 {
+            return;
+        }
+
+        let details = self.metadata.castling_details;
+        for spc in [SpecialMove::CastlingWestward, SpecialMove::CastlingEastward] {
+            let cmv = castling_move(spc, mover, details);
+            let (king, rook) = (cmv.king_move, cmv.rook_move);
+            let rest = occupied & !(king.to.bit() | rook.to.bit());
+
+            if moved.kings & king.to.bit() != 0
+                && moved.rooks & rook.to.bit() != 0
+                && (king.from.bit() | rook.from.bit() | cmv.clear_mask) & rest == 0
+            {
+                let pmv = if details.capture_own_rook {
+                    king.from.to(rook.from)
+                } else {
+                    king
+                };
+                res.push(UnMove {
+                    spc: Some(spc),
+                    ..UnMove::quiet(ChessPiece::King, pmv)
+                });
+            }
+        }
+    }
+
+    /// Whether `um` agrees with the castling rights, en passant square
+    /// and halfmove clock it left behind
+    fn consistent_unmove(&self, um: UnMove) -> bool {
+        let metadata = &self.metadata;
+        let mover = metadata.to_move.opposite();
+        let rights = metadata.castling_rights;
+        let details = metadata.castling_details;
+
+        let home_rook = |right: bool, file: BoardFile| right && um.pmv.to == file.by(mover.rank());
+        if rights.get(mover) != 0
+            && (um.piece == ChessPiece::King
+                || um.piece == ChessPiece::Rook
+                    && um.spc.is_none()
+                    && (home_rook(rights.westward(mover), details.westward.rook_from)
+                        || home_rook(rights.eastward(mover), details.eastward.rook_from)))
+        {
+            return false;
+        }
+
+        if let Some(ep) = metadata.en_passant {
+            let double = um.pmv.from.ix().abs_diff(um.pmv.to.ix()) == 16;
+            if um.piece != ChessPiece::Pawn
+                || um.uncapture.is_some()
+                || !double
+                || um.pmv.from.ix() + um.pmv.to.ix() != 2 * ep.ix()
+            {
+                return false;
+            }
+        }
+
+        metadata.halfmove_clock == 0 || !um.irreversible()
+    }
+}
+
+/// Whether the side that just moved left a legal position, with
+/// material a game could have
+fn legal_retraction(prev: &BitBoard, um: UnMove) -> bool {
+    let mover = prev.metadata.to_move;
+    let (moved, other) = prev.active_passive(mover);
+    if moved.attacks(mover, other.total) & other.kings != 0
+        || !plausible_material(moved)
+        || !plausible_material(other)
+    {
+        return false;
+    }
+
+    match um.spc {
+        Some(spc @ (SpecialMove::CastlingWestward | SpecialMove::CastlingEastward)) => {
+            let cmv = castling_move(spc, mover, prev.metadata.castling_details);
+            other.attacks(mover.opposite(), moved.total) & cmv.threat_mask == 0
+        }
+        _ => true,
+    }
+}
+
+/// At most sixteen pieces, with no more promoted pieces than missing pawns
+fn plausible_material(half: &HalfBitBoard) -> bool {
+    let extra = |p: ChessPiece, n: u32| half.mask(p).count_ones().saturating_sub(n);
+    let promoted = extra(ChessPiece::Knight, 2)
+        + extra(ChessPiece::Bishop, 2)
+        + extra(ChessPiece::Rook, 2)
+        + extra(ChessPiece::Queen, 1);
+
+    half.total.count_ones() <= 16 && half.pawns.count_ones() + promoted <= 8
+}
+
+fn castling_move(spc: SpecialMove, c: Color, details: CastlingDetails) -> CastlingMove {
+    match spc {
+        SpecialMove::CastlingWestward => details.westward.reify(c),
+        _ => details.eastward.reify(c),
+    }
+}
+
+/// The single right a castling move used up
+fn castling_right(spc: SpecialMove, c: Color) -> CastlingRights {
+    let own = CastlingRights::full().move_king(c.opposite());
+    match spc {
+        SpecialMove::CastlingWestward => own.move_east_rook(c),
+        _ => own.move_west_rook(c),
+    }
+}
+
+/// Empty squares from which `p` could have reached `to` without capturing
+fn unmove_origins(p: ChessPiece, c: Color, to: Square, occupied: BoardMask) -> BoardMask {
+    let origins = match p {
+        ChessPiece::Pawn => {
+            let (start, double) = match c {
+                Color::White => (BoardRank::_2, BoardRank::_4),
+                Color::Black => (BoardRank::_7, BoardRank::_5),
+            };
+            let single = behind(to.bit(), c) & !occupied & !start_rank_behind(c);
+            let double = if to.file_rank().1 == double && single != 0 {
+                behind(single, c) & start.mask()
+            } else {
+                0
+            };
+            single | double
+        }
+        ChessPiece::Knight => KNIGHT_MOVES.at(to),
+        ChessPiece::Bishop => bishop_rays(to, occupied),
+        ChessPiece::Rook => rook_rays(to, occupied),
+        ChessPiece::Queen => queen_rays(to, occupied),
+        ChessPiece::King => KING_MOVES.at(to),
+    };
+    origins & !occupied
+}
+
+/// One rank towards `c`'s own side of the board
+const fn behind(m: BoardMask, c: Color) -> BoardMask {
+    match c {
+        Color::White => m >> 8,
+        Color::Black => m << 8,
+    }
+}
+
+const fn ahead(m: BoardMask, c: Color) -> BoardMask {
+    behind(m, c.opposite())
+}
+
+/// Where `c`'s pawns land when capturing en passant
+const fn en_passant_rank(c: Color) -> BoardRank {
+    match c {
+        Color::White => BoardRank::_6,
+        Color::Black => BoardRank::_3,
+    }
+}
+
+fn on_edge_rank(sq: Square) -> bool {
+    matches!(sq.file_rank().1, BoardRank::_1 | BoardRank::_8)
+}
+
+/// Pawns can never stand on their own first rank
+fn start_rank_behind(c: Color) -> BoardMask {
+    match c {
+        Color::White => BoardRank::_1.mask(),
+        Color::Black => BoardRank::_8.mask(),
+    }
+}
+
+/// Whether the side to move was not in check before `pmv`
+fn legal_predecessor(
+    moved: &HalfBitBoard,
+    other: &HalfBitBoard,
+    p: ChessPiece,
+    pmv: PseudoMove,
+    mover: Color,
+) -> bool {
+    let mut before = moved.clone();
+    *before.piece(p) ^= pmv.bits();
+    before.total ^= pmv.bits();
+    before.attacks(mover, other.total) & other.kings == 0
+}
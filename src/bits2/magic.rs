@@ -0,0 +1,149 @@
+use std::sync::LazyLock;
+
+use crate::{
+    arrays::ArrayBoard,
+    bits::{BoardMask, Squares, rays},
+    bits2::{relevant_mask, subsets},
+    model::Square,
+};
+
+pub static MAGICS: LazyLock<MagicTable> = LazyLock::new(MagicTable::new);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Magic {
+    pub mask: BoardMask,
+    pub magic: u64,
+    pub shift: u32,
+    pub offset: usize,
+}
+
+impl Magic {
+    #[inline]
+    pub const fn index(&self, occupied: BoardMask) -> usize {
+        self.offset + ((occupied & self.mask).wrapping_mul(self.magic) >> self.shift) as usize
+    }
+}
+
+const SEED: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// Marsaglia's xorshift64, so the multipliers found do not depend on `rand`
+struct XorShift(u64);
+
+impl XorShift {
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+/// Magic multipliers for every square, found at startup, sharing one attack table
+#[derive(Debug, Clone)]
+pub struct MagicTable {
+    pub rooks: ArrayBoard<Magic>,
+    pub bishops: ArrayBoard<Magic>,
+    pub attacks: Vec<BoardMask>,
+}
+
+impl MagicTable {
+    pub fn new() -> Self {
+        let mut rng = XorShift(SEED);
+        let empty = Magic {
+            mask: 0,
+            magic: 0,
+            shift: 0,
+            offset: 0,
+        };
+
+        let mut res = MagicTable {
+            rooks: ArrayBoard::new(empty),
+            bishops: ArrayBoard::new(empty),
+            attacks: vec![],
+        };
+
+        for sq in Squares(BoardMask::MAX) {
+            let rook = find_magic(sq, rays::rook_rays, &mut rng, &mut res.attacks);
+            res.rooks.set(sq, rook);
+            let bishop = find_magic(sq, rays::bishop_rays, &mut rng, &mut res.attacks);
+            res.bishops.set(sq, bishop);
+        }
+
+        res
+    }
+}
+
+impl Default for MagicTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Tries sparse random multipliers until one maps every relevant occupancy
+/// to a slot holding its attacks, then appends those slots to `attacks`
+fn find_magic(
+    sq: Square,
+    slider: fn(Square, BoardMask) -> BoardMask,
+    rng: &mut XorShift,
+    attacks: &mut Vec<BoardMask>,
+) -> Magic {
+    let mask = relevant_mask(sq, slider);
+    let bits = mask.count_ones();
+    let occupancies = subsets(mask);
+    let reference = occupancies
+        .iter()
+        .map(|occ| slider(sq, *occ))
+        .collect::<Vec<_>>();
+
+    // Slider attacks are never empty, so zero marks an unused slot
+    let mut table = vec![0; 1 << bits];
+
+    loop {
+        let magic = rng.next_u64() & rng.next_u64() & rng.next_u64();
+        if (mask.wrapping_mul(magic) >> 56).count_ones() < 6 {
+            continue;
+        }
+
+        let candidate = Magic {
+            mask,
+            magic,
+            shift: 64 - bits,
+            offset: 0,
+        };
+
+        table.fill(0);
+        let fits = occupancies.iter().zip(&reference).all(|(occ, att)| {
+            let slot = &mut table[candidate.index(*occ)];
+            if *slot == 0 {
+                *slot = *att;
+            }
+            *slot == *att
+        });
+
+        if fits {
+            let offset = attacks.len();
+            attacks.extend_from_slice(&table);
+            return Magic {
+                offset,
+                ..candidate
+            };
+        }
+    }
+}
+
+#[inline]
+pub fn rook_rays(sq: Square, occupied: BoardMask) -> BoardMask {
+    let magics = &*MAGICS;
+    magics.attacks[magics.rooks.at(sq).index(occupied)]
+}
+
+#[inline]
+pub fn bishop_rays(sq: Square, occupied: BoardMask) -> BoardMask {
+    let magics = &*MAGICS;
+    magics.attacks[magics.bishops.at(sq).index(occupied)]
+}
+
+#[inline]
+pub fn queen_rays(sq: Square, occupied: BoardMask) -> BoardMask {
+    rook_rays(sq, occupied) | bishop_rays(sq, occupied)
+}
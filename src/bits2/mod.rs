@@ -0,0 +1,56 @@
+#[cfg(not(any(feature = "magic", all(feature = "pext", target_arch = "x86_64"))))]
+pub use crate::bits::rays::{bishop_rays, queen_rays, rook_rays};
+use crate::{
+    bits::{BoardMask, rays},
+    model::{BoardFile, BoardRank, Square},
+};
+#[cfg(all(feature = "magic", not(all(feature = "pext", target_arch = "x86_64"))))]
+pub use magic::{bishop_rays, queen_rays, rook_rays};
+#[cfg(all(feature = "pext", target_arch = "x86_64"))]
+pub use pext::{bishop_rays, queen_rays, rook_rays};
+
+pub mod magic;
+#[cfg(all(feature = "pext", target_arch = "x86_64"))]
+pub mod pext;
+pub mod tests;
+
+/// Which sliding-attack implementation the move generator uses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Sliders {
+    /// Obstruction difference, see `bits::rays`
+    Rays,
+    /// Magic bitboard lookup
+    Magic,
+    /// BMI2 parallel bit extract lookup
+    Pext,
+}
+
+impl Sliders {
+    /// Picked at compile time by the `magic` and `pext` features, with `pext` winning
+    pub const SELECTED: Sliders = if cfg!(all(feature = "pext", target_arch = "x86_64")) {
+        Sliders::Pext
+    } else if cfg!(feature = "magic") {
+        Sliders::Magic
+    } else {
+        Sliders::Rays
+    };
+}
+
+/// Squares whose occupancy can change the attacks from `sq`,
+/// leaving out the board edges the attacks end on anyway
+pub fn relevant_mask(sq: Square, attacks: fn(Square, BoardMask) -> BoardMask) -> BoardMask {
+    let ranks = (BoardRank::_1.mask() | BoardRank::_8.mask()) & !rays::rank_ray(sq);
+    let files = (BoardFile::A.mask() | BoardFile::H.mask()) & !rays::file_ray(sq);
+    attacks(sq, 0) & !(ranks | files)
+}
+
+/// Every subset of `mask`, counting up through its bits
+pub fn subsets(mask: BoardMask) -> Vec<BoardMask> {
+    let mut res = vec![0];
+    let mut sub = mask.wrapping_neg() & mask;
+    while sub != 0 {
+        res.push(sub);
+        sub = sub.wrapping_sub(mask) & mask;
+    }
+    res
+}
@@ -0,0 +1,88 @@
+use std::{arch::x86_64::_pext_u64, sync::LazyLock};
+
+use crate::{
+    arrays::ArrayBoard,
+    bits::{BoardMask, Squares, rays},
+    bits2::{relevant_mask, subsets},
+    model::Square,
+};
+
+pub static PEXT: LazyLock<PextTable> = LazyLock::new(|| {
+    assert!(supported(), "PEXT lookup needs a CPU with BMI2");
+    PextTable::new()
+});
+
+pub fn supported() -> bool {
+    is_x86_feature_detected!("bmi2")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PextEntry {
+    pub mask: BoardMask,
+    pub offset: usize,
+}
+
+impl PextEntry {
+    #[inline]
+    fn index(&self, occupied: BoardMask) -> usize {
+        // SAFETY: `PEXT` refuses to build without BMI2, and every entry comes from it
+        self.offset + unsafe { _pext_u64(occupied, self.mask) } as usize
+    }
+}
+
+/// Attacks indexed by the relevant occupancy bits packed together,
+/// needing no multipliers since `subsets` counts up in that order
+#[derive(Debug, Clone)]
+pub struct PextTable {
+    pub rooks: ArrayBoard<PextEntry>,
+    pub bishops: ArrayBoard<PextEntry>,
+    pub attacks: Vec<BoardMask>,
+}
+
+impl PextTable {
+    fn new() -> Self {
+        let empty = PextEntry { mask: 0, offset: 0 };
+        let mut res = PextTable {
+            rooks: ArrayBoard::new(empty),
+            bishops: ArrayBoard::new(empty),
+            attacks: vec![],
+        };
+
+        for sq in Squares(BoardMask::MAX) {
+            let rook = fill_entry(sq, rays::rook_rays, &mut res.attacks);
+            res.rooks.set(sq, rook);
+            let bishop = fill_entry(sq, rays::bishop_rays, &mut res.attacks);
+            res.bishops.set(sq, bishop);
+        }
+
+        res
+    }
+}
+
+fn fill_entry(
+    sq: Square,
+    slider: fn(Square, BoardMask) -> BoardMask,
+    attacks: &mut Vec<BoardMask>,
+) -> PextEntry {
+    let mask = relevant_mask(sq, slider);
+    let offset = attacks.len();
+    attacks.extend(subsets(mask).into_iter().map(|occ| slider(sq, occ)));
+    PextEntry { mask, offset }
+}
+
+#[inline]
+pub fn rook_rays(sq: Square, occupied: BoardMask) -> BoardMask {
+    let pext = &*PEXT;
+    pext.attacks[pext.rooks.at(sq).index(occupied)]
+}
+
+#[inline]
+pub fn bishop_rays(sq: Square, occupied: BoardMask) -> BoardMask {
+    let pext = &*PEXT;
+    pext.attacks[pext.bishops.at(sq).index(occupied)]
+}
+
+#[inline]
+pub fn queen_rays(sq: Square, occupied: BoardMask) -> BoardMask {
+    rook_rays(sq, occupied) | bishop_rays(sq, occupied)
+}
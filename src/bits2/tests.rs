@@ -0,0 +1,121 @@
+use std::{hint::black_box, time::Instant};
+
+use rand::RngCore;
+
+use crate::{
+    bits::{BoardMask, Squares, rays},
+    bits2::{Sliders, magic, relevant_mask, subsets},
+    fuzzing::pi_rng,
+    model::Square,
+    notation::fen::parse_fen,
+};
+
+#[cfg(test)]
+const PERFT_FENS: &[&str] = &[
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+];
+
+#[test]
+fn slider_subsets() {
+    let mask = relevant_mask(Square::a1, rays::rook_rays);
+    assert_eq!(mask.count_ones(), 12);
+
+    let subs = subsets(mask);
+    assert_eq!(subs.len(), 1 << 12);
+    assert!(subs.iter().all(|s| s & !mask == 0));
+    assert!(subs.is_sorted());
+}
+
+#[test]
+fn slider_lookups_match_rays() {
+    let mut rng = pi_rng();
+
+    for _ in 0..100 {
+        let total = rng.next_u64() & rng.next_u64();
+        for sq in Squares(BoardMask::MAX) {
+            let total = total | sq.bit();
+
+            assert_eq!(magic::rook_rays(sq, total), rays::rook_rays(sq, total));
+            assert_eq!(magic::bishop_rays(sq, total), rays::bishop_rays(sq, total));
+            assert_eq!(magic::queen_rays(sq, total), rays::queen_rays(sq, total));
+
+            #[cfg(all(feature = "pext", target_arch = "x86_64"))]
+            if crate::bits2::pext::supported() {
+                use crate::bits2::pext;
+                assert_eq!(pext::rook_rays(sq, total), rays::rook_rays(sq, total));
+                assert_eq!(pext::bishop_rays(sq, total), rays::bishop_rays(sq, total));
+                assert_eq!(pext::queen_rays(sq, total), rays::queen_rays(sq, total));
+            }
+        }
+    }
+}
+
+#[test]
+fn slider_perft() {
+    for (fen, nodes) in PERFT_FENS.iter().zip([8902, 97862, 2812]) {
+        let board = parse_fen(fen).unwrap();
+        assert_eq!(
+            board.enumerate(3).total(),
+            nodes,
+            "{:?} on {fen}",
+            Sliders::SELECTED
+        );
+    }
+}
+
+#[cfg(test)]
+type Slider = fn(Square, BoardMask) -> BoardMask;
+
+/// Compares the implementations on raw lookups and the selected one on perft,
+/// run with `--release --ignored --nocapture` and each feature for meaningful numbers
+#[test]
+#[ignore]
+fn bench_sliders() {
+    let mut rng = pi_rng();
+    let occupancies = (0..1000)
+        .map(|_| rng.next_u64() & rng.next_u64())
+        .collect::<Vec<_>>();
+
+    let mut lookups: Vec<(&str, Slider, Slider)> = vec![
+        ("Rays", rays::rook_rays, rays::bishop_rays),
+        ("Magic", magic::rook_rays, magic::bishop_rays),
+    ];
+    #[cfg(all(feature = "pext", target_arch = "x86_64"))]
+    if crate::bits2::pext::supported() {
+        lookups.push((
+            "Pext",
+            crate::bits2::pext::rook_rays,
+            crate::bits2::pext::bishop_rays,
+        ));
+    }
+
+    for (name, rook, bishop) in lookups {
+        black_box(rook(Square::a1, 0) ^ bishop(Square::a1, 0));
+        let now = Instant::now();
+        let mut acc = 0;
+        for occ in &occupancies {
+            for sq in Squares(BoardMask::MAX) {
+                acc ^= rook(sq, *occ) ^ bishop(sq, *occ);
+            }
+        }
+        black_box(acc);
+        println!(
+            "{name}: {} lookups in {:?}",
+            occupancies.len() * 128,
+            now.elapsed()
+        );
+    }
+
+    let now = Instant::now();
+    let nodes = PERFT_FENS
+        .iter()
+        .map(|fen| parse_fen(fen).unwrap().enumerate(3).total())
+        .sum::<usize>();
+    println!(
+        "{:?}: {nodes} perft nodes in {:?}",
+        Sliders::SELECTED,
+        now.elapsed()
+    );
+}
@@ -32,7 +32,8 @@ use tokio::{
 
 use crate::profile::EngineProfile;
 
-mod ucio;
+pub mod tests;
+pub mod ucio;
 
 pub async fn load_engine(prof: &EngineProfile) -> tokio::io::Result<(EngineHandle, EngineDetails)> {
     let mut handle = EngineHandle::open(
@@ -101,19 +102,51 @@ impl EngineDetails {
     }
 
     pub fn load_profile(&mut self, prof: &EngineProfile) {
-        use OptionType::*;
         for (key, optval) in &prof.options {
-            if let Some(opt) = self.options.get_mut(key) {
-                match (&mut opt.option_type, optval) {
-                    (Check(ct), OptVal::Check(b)) => ct.value = Some(*b),
-                    (Spin(st), OptVal::Spin(n)) => st.value = Some(*n),
-                    (Combo(ct), OptVal::StringOrCombo(s)) if ct.variants.contains(s) => {
-                        ct.value = Some(s.clone())
-                    }
-                    (String(st), OptVal::StringOrCombo(s)) => st.value = Some(s.clone()),
-                    _ => {}
-                }
+            self.set_option(key, optval);
+        }
+    }
+
+    /// Spin values are clamped to the bounds the engine announced, as
+    /// engines may reject or misbehave on values outside them
+    pub fn set_option(&mut self, key: &str, optval: &OptVal) -> bool {
+        use OptionType::*;
+        let Some(opt) = self.options.get_mut(key) else {
+            return false;
+        };
+
+        match (&mut opt.option_type, optval) {
+            (Check(ct), OptVal::Check(b)) => ct.value = Some(*b),
+            (Spin(st), OptVal::Spin(n)) => st.value = Some((*n).clamp(st.min, st.max)),
+            (Combo(ct), OptVal::StringOrCombo(s)) if ct.variants.contains(s) => {
+                ct.value = Some(s.clone())
             }
+            (String(st), OptVal::StringOrCombo(s)) => st.value = Some(s.clone()),
+            (Button(_), OptVal::Button()) => {}
+            _ => return false,
+        }
+
+        true
+    }
+
+    pub fn check(&self, key: &str) -> Option<bool> {
+        match &self.options.get(key)?.option_type {
+            OptionType::Check(ct) => Some(ct.value.unwrap_or(ct.default)),
+            _ => None,
+        }
+    }
+
+    pub fn string(&self, key: &str) -> Option<String> {
+        match &self.options.get(key)?.option_type {
+            OptionType::String(st) => Some(st.value.clone().unwrap_or(st.default.clone())),
+            _ => None,
+        }
+    }
+
+    pub fn spin(&self, key: &str) -> Option<i64> {
+        match &self.options.get(key)?.option_type {
+            OptionType::Spin(st) => Some(st.value.unwrap_or(st.default)),
+            _ => None,
         }
     }
 
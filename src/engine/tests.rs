@@ -0,0 +1,118 @@
+use crate::{
+    deque,
+    engine::{
+        EngineDetails,
+        ucio::{UciServer, score_string},
+    },
+    eval::EvalParams,
+    model::Square,
+    notation::{
+        fen::parse_fen,
+        uci::{
+            engine::{InfoString, ScoreBound, ScoreString, UciEngine},
+            gui::{OptVal, PositionString, UciGui},
+        },
+    },
+    search::MATE,
+};
+
+#[test]
+fn uci_server_position() {
+    let mut server = UciServer::new();
+    let line = vec![(Square::e2.to(Square::e4), None), (Square::e7.to(Square::e5), None)];
+
+    server.position(&PositionString::Startpos(), &line).unwrap();
+    assert_eq!(
+        server.board,
+        parse_fen("rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2").unwrap()
+    );
+    assert_eq!(server.history.len(), 2);
+
+    let illegal = vec![(Square::e2.to(Square::e5), None)];
+    assert!(
+        server
+            .position(&PositionString::Startpos(), &illegal)
+            .is_err()
+    );
+    assert_eq!(server.history.len(), 2);
+}
+
+#[test]
+fn uci_server_options() {
+    let mut server = UciServer::new();
+
+    assert!(server.details.set_option("Hash", &OptVal::Spin(1 << 20)));
+    assert_eq!(server.details.spin("Hash"), Some(65536));
+    assert!(!server.details.set_option("Hash", &OptVal::Check(true)));
+    assert!(!server.details.set_option("Contempt", &OptVal::Spin(10)));
+
+    let mut params = EvalParams::default();
+    params.middlegame.material.pawn = 120;
+    let path = std::env::temp_dir().join(format!("mintymacks-eval-{}.toml", std::process::id()));
+    params.save(&path).unwrap();
+
+    let file = OptVal::StringOrCombo(path.to_string_lossy().to_string());
+    assert!(server.details.set_option("EvalFile", &file));
+    server.option_changed("EvalFile").unwrap();
+    assert_eq!(*server.params, params);
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(server.option_changed("EvalFile").is_err());
+    let empty = OptVal::StringOrCombo("<empty>".to_string());
+    assert!(server.details.set_option("EvalFile", &empty));
+    server.option_changed("EvalFile").unwrap();
+    assert_eq!(*server.params, EvalParams::default());
+
+    let dir =
+        OptVal::StringOrCombo(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/syzygy").to_string());
+    assert!(server.details.set_option("SyzygyPath", &dir));
+    server.option_changed("SyzygyPath").unwrap();
+    assert_eq!(server.tablebase.as_ref().map(|tb| tb.max_pieces), Some(4));
+
+    let missing = OptVal::StringOrCombo("tests/no-such-dir".to_string());
+    assert!(server.details.set_option("SyzygyPath", &missing));
+    assert!(server.option_changed("SyzygyPath").is_err());
+    assert!(server.details.set_option("SyzygyPath", &empty));
+    server.option_changed("SyzygyPath").unwrap();
+    assert!(server.tablebase.is_none());
+}
+
+#[test]
+fn engine_details_clamp_spin() {
+    let threads =
+        UciEngine::from_string("option name Threads type spin default 1 min 1 max 8".to_string());
+    let mut details = EngineDetails::new(&[threads]);
+
+    assert!(details.set_option("Threads", &OptVal::Spin(0)));
+    assert_eq!(details.spin("Threads"), Some(1));
+    assert!(details.set_option("Threads", &OptVal::Spin(100)));
+    assert_eq!(details.spin("Threads"), Some(8));
+    assert_eq!(
+        details.set_options(),
+        deque![UciGui::SetOption("Threads".to_string(), OptVal::Spin(8))]
+    );
+}
+
+#[test]
+fn uci_score_strings() {
+    assert_eq!(score_string(35), ScoreString::Centipawns(35));
+    assert_eq!(score_string(MATE - 1), ScoreString::MateIn(1));
+    assert_eq!(score_string(MATE - 3), ScoreString::MateIn(2));
+    assert_eq!(score_string(-MATE + 2), ScoreString::MateIn(-1));
+
+    assert_eq!(
+        UciEngine::from_string("info score mate -2".to_string()),
+        UciEngine::Info(vec![InfoString::Score(
+            ScoreBound::Precise,
+            ScoreString::MateIn(-2)
+        )])
+    );
+    assert_eq!(
+        UciEngine::Info(vec![InfoString::Score(
+            ScoreBound::Precise,
+            score_string(-MATE + 4)
+        )])
+        .to_string(),
+        "info score mate -2\n"
+    );
+}
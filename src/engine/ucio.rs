@@ -1,42 +1,58 @@
-use std::time::Duration;
+use std::{path::Path, sync::Arc};
 
+use indexmap::IndexMap;
 use tokio::{
-    io::{
-        AsyncBufRead, AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter, Stdin, Stdout, stdin,
-        stdout,
-    },
-    select,
-    sync::mpsc::{UnboundedReceiver, UnboundedSender},
-    time::sleep,
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter, Stdin, Stdout, stdin, stdout},
+    sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel},
+    task::JoinHandle,
 };
-use tokio_util::sync::CancellationToken;
+use tokio_util::sync::{CancellationToken, DropGuard};
 
 use crate::{
-    deque,
-    engine::{EngineDetails, ucio},
-    notation::uci::{
-        engine::{IdString, InfoString, UciEngine},
-        gui::UciGui,
+    bits::board::BitBoard,
+    engine::EngineDetails,
+    eval::EvalParams,
+    model::moves::PseudoMove,
+    notation::{
+        fen::parse_fen_6,
+        uci::{
+            Line,
+            engine::{
+                BestMove, ButtonType, CheckType, EngineOption, IdString, InfoString, OptionType,
+                ScoreBound, ScoreString, SpinType, StringType, UciEngine,
+            },
+            gui::{GoCommand, PositionString, UciGui},
+        },
+    },
+    search::{
+        DEFAULT_HASH_MB, Score, Search, SearchLimits, SearchResult,
+        mate::{MateSearch, Stipulation},
+        mate_distance,
     },
+    tablebase::syzygy::Syzygy,
+    zobrist::{ZobHash, table::ZobTable},
 };
 
 pub struct GoStopInfo {
-    receiver: UnboundedReceiver<Vec<InfoString>>,
-    sender: UnboundedSender<Vec<InfoString>>,
-    stop: tokio_util::sync::CancellationToken,
-    _drop: tokio_util::sync::DropGuard,
+    sender: UnboundedSender<UciEngine>,
+    stop: CancellationToken,
+    _drop: DropGuard,
+    discard: CancellationToken,
+    search: Option<JoinHandle<()>>,
+    ponder: Option<Search>,
 }
 
 impl GoStopInfo {
-    pub fn new() -> Self {
-        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+    pub fn new(sender: UnboundedSender<UciEngine>) -> Self {
         let stop = CancellationToken::new();
         let _drop = stop.clone().drop_guard();
         GoStopInfo {
-            receiver,
             sender,
             stop,
             _drop,
+            discard: CancellationToken::new(),
+            search: None,
+            ponder: None,
         }
     }
 
@@ -47,47 +63,116 @@ impl GoStopInfo {
         }
     }
 
-    pub fn react(&mut self, uci: &UciGui) -> bool {
-        match uci {
-            UciGui::Go(_) | UciGui::PonderHit() | UciGui::UciNewGame() => {
-                self.stop.cancel();
-                self.receiver.close();
-                self.stop = CancellationToken::new();
-                self._drop = self.stop.clone().drop_guard();
-                true
+    pub fn is_searching(&self) -> bool {
+        self.search.as_ref().is_some_and(|h| !h.is_finished())
+    }
+
+    pub fn is_pondering(&self) -> bool {
+        self.ponder.is_some()
+    }
+
+    /// Runs `search` on a blocking thread, answering with `bestmove` when it
+    /// finishes; `wait` holds the answer back until the search is stopped
+    pub fn go(&mut self, mut search: Search, wait: bool) {
+        let out = self.ucout();
+        let discard = self.discard.clone();
+        let table = search.table.clone();
+
+        self.search = Some(tokio::spawn(async move {
+            let report = out.clone();
+            let res =
+                tokio::task::spawn_blocking(move || search.run(|res| report.report(res, &table)))
+                    .await;
+
+            if wait {
+                out.stop.cancelled().await;
             }
-            UciGui::Quit() => {
-                self.stop.cancel();
-                self.receiver.close();
-                true
+
+            if let Ok(res) = res
+                && !discard.is_cancelled()
+            {
+                out.best_move(&res);
             }
-            _ => false,
-        }
+        }));
     }
 
-    pub async fn listen(&mut self, timeout: Duration) -> tokio::io::Result<()> {
-        let mut ucin = Ucin::new();
-        let mut ucout = Ucout::new();
-        let mut outbox = deque![];
+    /// Looks for a forced mate in `n` moves, falling back to `search`
+    /// with whatever time is left when there is none
+    pub fn go_mate(&mut self, n: u64, mut search: Search) {
+        let out = self.ucout();
+        let discard = self.discard.clone();
+        let table = search.table.clone();
+        let mut mate = MateSearch::new(search.board.clone(), Stipulation::Direct, n as usize)
+            .with_stop(search.stop.clone())
+            .with_limit(search.limits.hard);
 
-        loop {
-            select! {
-                _ = sleep(timeout) => break,
-                _ = ucout.send(outbox.front()), if !outbox.is_empty() => {
-                    outbox.pop_front();
-                }
-                uci = ucin.receive() => {
-                    self.react(&(uci?));
-                }
-                uci = self.receiver.recv() => {
-                    if let Some(uci) = uci {
-                        outbox.push_back(UciEngine::Info(uci));
-                    }
+        self.search = Some(tokio::spawn(async move {
+            let report = out.clone();
+            let res = tokio::task::spawn_blocking(move || {
+                let found = mate.run();
+                if found.moves.is_some() {
+                    let res = found.search_result();
+                    report.report(&res, &table);
+                    res
+                } else {
+                    let limits = &mut search.limits;
+                    limits.soft = limits.soft.map(|t| t.saturating_sub(found.time));
+                    limits.hard = limits.hard.map(|t| t.saturating_sub(found.time));
+                    search.run(|res| report.report(res, &table))
                 }
+            })
+            .await;
+
+            if let Ok(res) = res
+                && !discard.is_cancelled()
+            {
+                out.best_move(&res);
             }
+        }));
+    }
+
+    /// Searches the predicted position without limits until `ponderhit`,
+    /// which restarts it as `search`, or `stop`
+    pub fn go_ponder(&mut self, search: Search) {
+        let pondering = Search::new(
+            search.board.clone(),
+            SearchLimits::default(),
+            search.table.clone(),
+        )
+        .with_history(search.history.clone())
+        .with_params(search.params.clone())
+        .with_multipv(search.multipv)
+        .with_threads(search.threads)
+        .with_stop(self.stop.clone());
+
+        self.go(pondering, true);
+        self.ponder = Some(search);
+    }
+
+    /// The opponent played the predicted move: the work done so far stays
+    /// in the transposition table for the timed search that replaces it
+    pub async fn ponder_hit(&mut self) {
+        let Some(search) = self.ponder.take() else {
+            return;
+        };
+
+        self.discard.cancel();
+        self.stop().await;
+        self.go(search.with_stop(self.stop.clone()), false);
+    }
+
+    /// Cancels the running search, if any, and waits for its `bestmove`
+    pub async fn stop(&mut self) {
+        self.stop.cancel();
+        self.ponder = None;
+
+        if let Some(search) = self.search.take() {
+            let _ = search.await;
         }
 
-        Ok(())
+        self.stop = CancellationToken::new();
+        self._drop = self.stop.clone().drop_guard();
+        self.discard = CancellationToken::new();
     }
 }
 
@@ -95,6 +180,12 @@ pub struct Ucin {
     reader: BufReader<Stdin>,
 }
 
+impl Default for Ucin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Ucin {
     pub fn new() -> Self {
         Self {
@@ -103,9 +194,16 @@ impl Ucin {
     }
 
     pub async fn receive(&mut self) -> tokio::io::Result<UciGui> {
+        Ok(UciGui::from_string(self.receive_line().await?))
+    }
+
+    /// End of input counts as `quit`
+    pub async fn receive_line(&mut self) -> tokio::io::Result<String> {
         let mut buf = String::new();
-        self.reader.read_line(&mut buf).await?;
-        Ok(UciGui::from_string(buf))
+        if self.reader.read_line(&mut buf).await? == 0 {
+            return Ok("quit".to_string());
+        }
+        Ok(buf)
     }
 }
 
@@ -113,6 +211,12 @@ pub struct Ucout {
     writer: BufWriter<Stdout>,
 }
 
+impl Default for Ucout {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Ucout {
     pub fn new() -> Self {
         Self {
@@ -122,17 +226,326 @@ impl Ucout {
 
     pub async fn send(&mut self, uci: Option<&UciEngine>) -> tokio::io::Result<()> {
         if let Some(uci) = uci {
-            self.writer.write_all(uci.to_string().as_bytes()).await?
+            self.writer.write_all(uci.to_string().as_bytes()).await?;
+            self.writer.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// Writes everything sent on `receiver` until all senders are gone
+    pub async fn drain(
+        mut self,
+        mut receiver: UnboundedReceiver<UciEngine>,
+    ) -> tokio::io::Result<()> {
+        while let Some(uci) = receiver.recv().await {
+            self.send(Some(&uci)).await?;
         }
         Ok(())
     }
 }
 
+#[derive(Clone)]
 pub struct Infout {
-    sender: UnboundedSender<Vec<InfoString>>,
+    sender: UnboundedSender<UciEngine>,
     stop: CancellationToken,
 }
 
+impl Infout {
+    /// A search for `go` that is cancelled by the next `stop`, `go` or `quit`
+    pub fn search(&self, board: BitBoard, go: &GoCommand, table: Arc<ZobTable>) -> Search {
+        let limits = SearchLimits::from_go(go, board.metadata.to_move);
+        Search::new(board, limits, table).with_stop(self.stop.clone())
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stop.is_cancelled()
+    }
+
+    pub fn send(&self, uci: UciEngine) {
+        let _ = self.sender.send(uci);
+    }
+
+    pub fn info(&self, info: Vec<InfoString>) {
+        self.send(UciEngine::Info(info));
+    }
+
+    pub fn string(&self, s: String) {
+        self.info(vec![InfoString::String(s)]);
+    }
+
+    /// One `info` line per principal variation, best first
+    pub fn report(&self, res: &SearchResult, table: &ZobTable) {
+        let millis = res.time.as_millis() as u64;
+        for (i, line) in res.lines.iter().enumerate() {
+            self.info(vec![
+                InfoString::Depth(res.depth as u64),
+                InfoString::MultiVariation(i as u64 + 1),
+                InfoString::Score(ScoreBound::Precise, score_string(line.score)),
+                InfoString::Nodes(res.nodes),
+                InfoString::NodesPerSecond(res.nodes * 1000 / millis.max(1)),
+                InfoString::Time(millis),
+                InfoString::HashFullPermill(table.hashfull()),
+                InfoString::PrincipleVariation(line.pv.iter().map(|mv| mv.simplify()).collect()),
+            ]);
+        }
+    }
+
+    /// Answers with the null move `0000` when there is nothing to play
+    pub fn best_move(&self, res: &SearchResult) {
+        self.send(UciEngine::BestMove(BestMove {
+            best: res
+                .best
+                .map(|mv| mv.simplify())
+                .unwrap_or((PseudoMove::NULL, None)),
+            ponder: res.ponder().map(|mv| mv.simplify()),
+        }));
+    }
+}
+
+pub fn score_string(score: Score) -> ScoreString {
+    match mate_distance(score) {
+        Some(n) if n > 0 => ScoreString::MateIn((n as i64 + 1) / 2),
+        Some(n) => ScoreString::MateIn(n as i64 / 2),
+        None => ScoreString::Centipawns(score as i64),
+    }
+}
+
+/// How UCI spells the default of a string option that is empty
+const EMPTY: &str = "<empty>";
+
+/// Engine side of the UCI protocol, playing with the in-crate search
+pub struct UciServer {
+    pub details: EngineDetails,
+    pub board: BitBoard,
+    pub history: Vec<ZobHash>,
+    pub table: Arc<ZobTable>,
+    pub params: Arc<EvalParams>,
+    pub tablebase: Option<Arc<Syzygy>>,
+}
+
+impl Default for UciServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UciServer {
+    pub fn new() -> Self {
+        let mut options = IndexMap::new();
+        for opt in [
+            EngineOption {
+                name: "Hash".to_string(),
+                option_type: OptionType::Spin(SpinType {
+                    default: DEFAULT_HASH_MB as i64,
+                    min: 1,
+                    max: 65536,
+                    value: None,
+                }),
+            },
+            EngineOption {
+                name: "Threads".to_string(),
+                option_type: OptionType::Spin(SpinType {
+                    default: 1,
+                    min: 1,
+                    max: 1024,
+                    value: None,
+                }),
+            },
+            EngineOption {
+                name: "MultiPV".to_string(),
+                option_type: OptionType::Spin(SpinType {
+                    default: 1,
+                    min: 1,
+                    max: 256,
+                    value: None,
+                }),
+            },
+            EngineOption {
+                name: "Ponder".to_string(),
+                option_type: OptionType::Check(CheckType {
+                    default: false,
+                    value: None,
+                }),
+            },
+            EngineOption {
+                name: "Clear Hash".to_string(),
+                option_type: OptionType::Button(ButtonType),
+            },
+            EngineOption {
+                name: "EvalFile".to_string(),
+                option_type: OptionType::String(StringType {
+                    default: EMPTY.to_string(),
+                    value: None,
+                }),
+            },
+            EngineOption {
+                name: "SyzygyPath".to_string(),
+                option_type: OptionType::String(StringType {
+                    default: EMPTY.to_string(),
+                    value: None,
+                }),
+            },
+        ] {
+            options.insert(opt.name.clone(), opt);
+        }
+
+        Self {
+            details: EngineDetails {
+                name: format!("mintymacks {}", env!("CARGO_PKG_VERSION")),
+                author: "Kile Asmussen".to_string(),
+                options,
+            },
+            board: BitBoard::startpos(),
+            history: vec![],
+            table: Arc::new(ZobTable::megabytes(DEFAULT_HASH_MB)),
+            params: Arc::new(EvalParams::default()),
+            tablebase: None,
+        }
+    }
+
+    pub async fn run(mut self) -> tokio::io::Result<()> {
+        let (sender, receiver) = unbounded_channel();
+        let output = tokio::spawn(Ucout::new().drain(receiver));
+        let mut ucin = Ucin::new();
+        let mut gostop = GoStopInfo::new(sender);
+
+        loop {
+            let line = ucin.receive_line().await?;
+            let out = gostop.ucout();
+
+            match UciGui::from_string(line.clone()) {
+                UciGui::Uci() => {
+                    for uci in self.details.dump() {
+                        out.send(uci);
+                    }
+                    out.send(UciEngine::UciOk());
+                }
+                UciGui::IsReady() => out.send(UciEngine::ReadyOk()),
+                UciGui::SetOption(name, value) => {
+                    gostop.stop().await;
+                    if self.details.set_option(&name, &value) {
+                        if let Err(e) = self.option_changed(&name) {
+                            out.string(e);
+                        }
+                    } else {
+                        out.string(format!("unknown option {name}"));
+                    }
+                }
+                UciGui::UciNewGame() => {
+                    gostop.stop().await;
+                    self.table.clear();
+                }
+                UciGui::Position(pos, line) => {
+                    gostop.stop().await;
+                    if let Err(e) = self.position(&pos, &line) {
+                        out.string(e);
+                    }
+                }
+                UciGui::Go(GoCommand::Perft(depth)) => {
+                    gostop.stop().await;
+                    self.perft(depth.unwrap_or(1) as usize, &out);
+                }
+                UciGui::Go(_) => {
+                    gostop.stop().await;
+                    let go = GoCommand::parse_all(&line);
+                    let limits = SearchLimits::from_go_all(&go, self.board.metadata.to_move);
+                    let mut search = Search::new(self.board.clone(), limits, self.table.clone())
+                        .with_history(self.history.clone())
+                        .with_params(self.params.clone())
+                        .with_multipv(self.details.spin("MultiPV").unwrap_or(1) as usize)
+                        .with_threads(self.details.spin("Threads").unwrap_or(1) as usize)
+                        .with_stop(gostop.ucout().stop);
+                    if let Some(tb) = &self.tablebase {
+                        search = search.with_tablebase(tb.clone());
+                    }
+
+                    if let Some(n) = go.iter().find_map(|go| match go {
+                        GoCommand::Mate(n) => Some(*n),
+                        _ => None,
+                    }) {
+                        gostop.go_mate(n, search);
+                    } else if go.contains(&GoCommand::Ponder()) {
+                        gostop.go_ponder(search);
+                    } else {
+                        gostop.go(search, go.contains(&GoCommand::Infinite()));
+                    }
+                }
+                UciGui::PonderHit() => gostop.ponder_hit().await,
+                UciGui::Stop() => gostop.stop().await,
+                UciGui::Quit() => {
+                    gostop.stop().await;
+                    break;
+                }
+                UciGui::Debug(_) | UciGui::Register(_) | UciGui::Custom(_) => {}
+            }
+        }
+
+        drop(gostop);
+        output.await?
+    }
+
+    pub fn option_changed(&mut self, name: &str) -> Result<(), String> {
+        match name {
+            "Hash" => {
+                let mb = self.details.spin("Hash").unwrap_or(DEFAULT_HASH_MB as i64);
+                self.table = Arc::new(ZobTable::megabytes(mb as usize));
+            }
+            "Clear Hash" => self.table.clear(),
+            "EvalFile" => {
+                let params = match self.details.string("EvalFile") {
+                    Some(path) if !path.is_empty() && path != EMPTY => {
+                        EvalParams::load(Path::new(&path))?
+                    }
+                    _ => EvalParams::default(),
+                };
+                self.params = Arc::new(params);
+            }
+            "SyzygyPath" => {
+                self.tablebase = match self.details.string("SyzygyPath") {
+                    Some(path) if !path.is_empty() && path != EMPTY => {
+                        Some(Arc::new(Syzygy::open(Path::new(&path))?))
+                    }
+                    _ => None,
+                };
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    pub fn position(&mut self, pos: &PositionString, line: &Line) -> Result<(), String> {
+        let mut board = match pos {
+            PositionString::Startpos() => BitBoard::startpos(),
+            PositionString::Fen(fen) => parse_fen_6(fen)?,
+        };
+        let mut history = vec![];
+        let mut moves = vec![];
+
+        for la in line {
+            moves.clear();
+            board.moves(&mut moves);
+            let Some(mv) = moves.iter().find(|mv| mv.simplify() == *la) else {
+                return Err(format!("illegal move {}", la.0.longalg(la.1)));
+            };
+            history.push(board.metadata.hash);
+            board.apply(*mv);
+        }
+
+        self.board = board;
+        self.history = history;
+        Ok(())
+    }
+
+    fn perft(&self, depth: usize, out: &Infout) {
+        let threads = self.details.spin("Threads").unwrap_or(1) as usize;
+        let res = self.board.enumerate_parallel(depth, threads);
+        for ((pmv, pr), n) in &res.moves {
+            out.string(format!("{}: {}", pmv.longalg(*pr), n));
+        }
+        out.string(format!("Nodes searched: {}", res.total()));
+    }
+}
+
 impl EngineDetails {
     pub fn dump(&self) -> Vec<UciEngine> {
         let mut res = vec![];
@@ -0,0 +1,190 @@
+pub mod nnue;
+pub mod safety;
+pub mod tables;
+pub mod tests;
+pub mod tuning;
+
+use serde::{Deserialize, Serialize};
+use strum::VariantArray;
+
+use crate::{
+    arrays::ArrayBoard,
+    bits::{
+        BoardMask, Squares,
+        attacks::pawn_attacks,
+        board::{BitBoard, HalfBitBoard},
+        jumps::KNIGHT_MOVES,
+        rays::{bishop_rays, queen_rays, rook_rays},
+    },
+    eval::tables::DEFAULT_PARAMS,
+    model::{ChessPiece, Color, Square},
+    search::Score,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct PieceTable<T> {
+    pub pawn: T,
+    pub knight: T,
+    pub bishop: T,
+    pub rook: T,
+    pub queen: T,
+    pub king: T,
+}
+
+impl<T> PieceTable<T> {
+    pub const fn get(&self, p: ChessPiece) -> &T {
+        match p {
+            ChessPiece::Pawn => &self.pawn,
+            ChessPiece::Knight => &self.knight,
+            ChessPiece::Bishop => &self.bishop,
+            ChessPiece::Rook => &self.rook,
+            ChessPiece::Queen => &self.queen,
+            ChessPiece::King => &self.king,
+        }
+    }
+
+    pub const fn get_mut(&mut self, p: ChessPiece) -> &mut T {
+        match p {
+            ChessPiece::Pawn => &mut self.pawn,
+            ChessPiece::Knight => &mut self.knight,
+            ChessPiece::Bishop => &mut self.bishop,
+            ChessPiece::Rook => &mut self.rook,
+            ChessPiece::Queen => &mut self.queen,
+            ChessPiece::King => &mut self.king,
+        }
+    }
+}
+
+/// Piece-square tables are laid out as seen from white's side
+/// of the board, eighth rank first, like `ArrayBoard::setup`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Weights {
+    pub material: PieceTable<i16>,
+    pub mobility: PieceTable<i16>,
+    pub squares: PieceTable<[[i16; 8]; 8]>,
+}
+
+impl Weights {
+    pub const fn square(&self, p: ChessPiece, c: Color, sq: Square) -> i16 {
+        let (file, rank) = sq.file_rank();
+        let row = match c {
+            Color::White => 7 - rank.ix(),
+            Color::Black => rank.ix(),
+        };
+        self.squares.get(p)[row as usize][file.ix() as usize]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EvalParams {
+    pub middlegame: Weights,
+    pub endgame: Weights,
+}
+
+impl Default for EvalParams {
+    fn default() -> Self {
+        DEFAULT_PARAMS
+    }
+}
+
+pub const PHASE: PieceTable<i32> = PieceTable {
+    pawn: 0,
+    knight: 1,
+    bishop: 1,
+    rook: 2,
+    queen: 4,
+    king: 0,
+};
+
+pub const MAX_PHASE: i32 = 24;
+
+/// White-relative scores for both game phases, before tapering
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Evaluation {
+    pub middlegame: Score,
+    pub endgame: Score,
+    pub phase: i32,
+}
+
+impl Evaluation {
+    pub const fn taper(self) -> Score {
+        (self.middlegame * self.phase + self.endgame * (MAX_PHASE - self.phase)) / MAX_PHASE
+    }
+}
+
+impl EvalParams {
+    pub fn evaluate(&self, board: &BitBoard) -> Score {
+        let score = self.evaluation(board).taper();
+        match board.metadata.to_move {
+            Color::White => score,
+            Color::Black => -score,
+        }
+    }
+
+    pub fn evaluation(&self, board: &BitBoard) -> Evaluation {
+        let (wmg, weg) = self.half(&board.white, Color::White, &board.black);
+        let (bmg, beg) = self.half(&board.black, Color::Black, &board.white);
+
+        Evaluation {
+            middlegame: wmg - bmg,
+            endgame: weg - beg,
+            phase: MAX_PHASE.min(board.white.phase() + board.black.phase()),
+        }
+    }
+
+    fn half(&self, friendly: &HalfBitBoard, c: Color, enemy: &HalfBitBoard) -> (Score, Score) {
+        let safe = !friendly.total & !pawn_attacks(enemy.pawns, c.opposite());
+        let (mg, eg) = (&self.middlegame, &self.endgame);
+
+        let mut materiel = [ArrayBoard([0; 64]), ArrayBoard([0; 64])];
+        friendly.materiel_by(|p| *mg.material.get(p), &mut materiel[0]);
+        friendly.materiel_by(|p| *eg.material.get(p), &mut materiel[1]);
+        let mut mg_score = materiel[0].sum(friendly.total) as Score;
+        let mut eg_score = materiel[1].sum(friendly.total) as Score;
+
+        for p in ChessPiece::VARIANTS {
+            let p = *p;
+            for sq in Squares(friendly.mask(p)) {
+                mg_score += mg.square(p, c, sq) as Score;
+                eg_score += eg.square(p, c, sq) as Score;
+            }
+
+            if matches!(p, ChessPiece::Pawn | ChessPiece::King) {
+                continue;
+            }
+            let mut attacks = ArrayBoard([0; 64]);
+            friendly.count_piece_attackers(p, c, 1, enemy.total, &mut attacks);
+            let mobility = attacks.sum(safe) as Score;
+            mg_score += *mg.mobility.get(p) as Score * mobility;
+            eg_score += *eg.mobility.get(p) as Score * mobility;
+        }
+
+        (mg_score, eg_score)
+    }
+}
+
+pub fn mobility(p: ChessPiece, sq: Square, occupied: BoardMask) -> BoardMask {
+    match p {
+        ChessPiece::Knight => KNIGHT_MOVES.at(sq),
+        ChessPiece::Bishop => bishop_rays(sq, occupied),
+        ChessPiece::Rook => rook_rays(sq, occupied),
+        ChessPiece::Queen => queen_rays(sq, occupied),
+        ChessPiece::Pawn | ChessPiece::King => BoardMask::MIN,
+    }
+}
+
+impl HalfBitBoard {
+    pub fn phase(&self) -> i32 {
+        ChessPiece::VARIANTS
+            .iter()
+            .map(|p| self.mask(*p).count_ones() as i32 * PHASE.get(*p))
+            .sum()
+    }
+}
+
+impl BitBoard {
+    /// Side-to-move relative centipawn score using the default weights
+    pub fn evaluate(&self) -> Score {
+        DEFAULT_PARAMS.evaluate(self)
+    }
+}
@@ -0,0 +1,253 @@
+use std::{
+    path::Path,
+    simd::{Simd, cmp::SimdOrd, num::SimdInt},
+    sync::Arc,
+};
+
+use strum::VariantArray;
+
+use crate::{
+    bits::{Squares, board::BitBoard},
+    model::{ChessPiece, Color, Square, castling::CastlingDetails, moves::ChessMove},
+    search::Score,
+    zobrist::{PieceDelta, piece_delta},
+};
+
+pub const MAGIC: &[u8; 4] = b"MMNN";
+pub const VERSION: u32 = 1;
+pub const FEATURES: usize = 768;
+pub const QA: i16 = 255;
+pub const QB: i32 = 64;
+pub const SCALE: i32 = 400;
+
+const LANES: usize = 16;
+type Lanes = Simd<i16, LANES>;
+
+/// A two-perspective network with a single hidden layer,
+/// loaded from a little-endian weight file laid out as:
+///
+/// | field           | type  | count         |
+/// |-----------------|-------|---------------|
+/// | magic `MMNN`    | u8    | 4             |
+/// | version, 1      | u32   | 1             |
+/// | hidden size `H` | u32   | 1             |
+/// | feature weights | i16   | 768 × H       |
+/// | hidden biases   | i16   | H             |
+/// | output weights  | i16   | 2 × H         |
+/// | output bias     | i32   | 1             |
+///
+/// `H` must be a multiple of 16. Feature weights are stored one row of `H` per
+/// input feature. Features are indexed from the point of view of each side as
+/// `384 × theirs + 64 × (piece - 1) + square`, where `theirs` is 1 for the
+/// opponent's pieces and squares are flipped vertically for black.
+///
+/// The first `H` output weights apply to the side to move, the rest to the
+/// opponent. Hidden values are clamped to `0..=QA`, and the output is scaled
+/// by `SCALE / (QA × QB)` to centipawns.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Network {
+    pub hidden: usize,
+    pub feature_weights: Vec<i16>,
+    pub hidden_biases: Vec<i16>,
+    pub output_weights: Vec<i16>,
+    pub output_bias: i32,
+}
+
+impl Network {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader(bytes);
+
+        if reader.take(4)? != MAGIC {
+            return Err("Not a network file".to_string());
+        }
+
+        let version = reader.u32()?;
+        if version != VERSION {
+            return Err(format!("Unsupported network version {version}"));
+        }
+
+        let hidden = reader.u32()? as usize;
+        if hidden == 0 || !hidden.is_multiple_of(LANES) {
+            return Err(format!("Hidden size {hidden} is not a multiple of {LANES}"));
+        }
+
+        let res = Self {
+            hidden,
+            feature_weights: reader.i16s(FEATURES * hidden)?,
+            hidden_biases: reader.i16s(hidden)?,
+            output_weights: reader.i16s(2 * hidden)?,
+            output_bias: reader.i32()?,
+        };
+
+        if !reader.0.is_empty() {
+            return Err(format!("{} trailing bytes in network file", reader.0.len()));
+        }
+
+        Ok(res)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut res = MAGIC.to_vec();
+        res.extend(VERSION.to_le_bytes());
+        res.extend((self.hidden as u32).to_le_bytes());
+        for x in self
+            .feature_weights
+            .iter()
+            .chain(&self.hidden_biases)
+            .chain(&self.output_weights)
+        {
+            res.extend(x.to_le_bytes());
+        }
+        res.extend(self.output_bias.to_le_bytes());
+        res
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        Self::from_bytes(&std::fs::read(path).map_err(|e| e.to_string())?)
+    }
+
+    fn row(&self, feature: usize) -> &[i16] {
+        &self.feature_weights[feature * self.hidden..(feature + 1) * self.hidden]
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        if self.0.len() < n {
+            return Err("Network file is truncated".to_string());
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i16s(&mut self, n: usize) -> Result<Vec<i16>, String> {
+        Ok(self
+            .take(2 * n)?
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect())
+    }
+}
+
+/// The index of a piece as seen by `perspective`
+pub fn feature(perspective: Color, c: Color, p: ChessPiece, sq: Square) -> usize {
+    let theirs = (c != perspective) as usize;
+    let sq = match perspective {
+        Color::White => sq.ix(),
+        Color::Black => sq.ix() ^ 56,
+    } as usize;
+    384 * theirs + 64 * (p as usize - 1) + sq
+}
+
+/// Hidden layer values from both sides' points of view
+#[derive(Clone, PartialEq, Eq)]
+pub struct Nnue {
+    pub network: Arc<Network>,
+    pub white: Vec<i16>,
+    pub black: Vec<i16>,
+}
+
+impl std::fmt::Debug for Nnue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Nnue({})", self.network.hidden)
+    }
+}
+
+impl Nnue {
+    pub fn new(network: Arc<Network>, board: &BitBoard) -> Self {
+        let mut res = Self {
+            white: network.hidden_biases.clone(),
+            black: network.hidden_biases.clone(),
+            network,
+        };
+
+        for c in [Color::White, Color::Black] {
+            let (half, _) = board.active_passive(c);
+            for p in ChessPiece::VARIANTS {
+                for sq in Squares(half.mask(*p)) {
+                    res.toggle(c, *p, sq, true);
+                }
+            }
+        }
+
+        res
+    }
+
+    fn toggle(&mut self, c: Color, p: ChessPiece, sq: Square, add: bool) {
+        let network = &self.network;
+        for (acc, perspective) in [(&mut self.white, Color::White), (&mut self.black, Color::Black)]
+        {
+            let row = network.row(feature(perspective, c, p, sq));
+            for (acc, row) in acc.chunks_exact_mut(LANES).zip(row.chunks_exact(LANES)) {
+                let (a, r) = (Lanes::from_slice(acc), Lanes::from_slice(row));
+                let sum = if add { a + r } else { a - r };
+                sum.copy_to_slice(acc);
+            }
+        }
+    }
+
+    /// Applies the piece movements of `mv`, undoing them if `forward` is false
+    pub fn delta(&mut self, mv: ChessMove, details: CastlingDetails, forward: bool) {
+        piece_delta(
+            mv,
+            details,
+            &mut Accumulate {
+                nnue: self,
+                forward,
+            },
+        );
+    }
+
+    /// Centipawns relative to `to_move`
+    pub fn evaluate(&self, to_move: Color) -> Score {
+        let (us, them) = match to_move {
+            Color::White => (&self.white, &self.black),
+            Color::Black => (&self.black, &self.white),
+        };
+        let (w_us, w_them) = self.network.output_weights.split_at(self.network.hidden);
+
+        let sum = dot(us, w_us) + dot(them, w_them) + self.network.output_bias as i64;
+        (sum * SCALE as i64 / (QA as i64 * QB as i64)) as Score
+    }
+}
+
+/// Feeds the per-piece deltas `ZOBRIST.delta` uses into the accumulators.
+///
+/// This runs from `Search::make`/`unmake` rather than `BitBoard::apply`/
+/// `unapply`: the accumulators are as wide as the hidden layer, and boards
+/// are copied freely by perft, the tablebases and the mate solver, none of
+/// which evaluate. Only the search owns an `Nnue`, so it applies the same
+/// delta right after each board move instead.
+struct Accumulate<'a> {
+    nnue: &'a mut Nnue,
+    forward: bool,
+}
+
+impl PieceDelta for Accumulate<'_> {
+    fn piece(&mut self, c: Color, p: ChessPiece, sq: Square, added: bool) {
+        self.nnue.toggle(c, p, sq, added == self.forward);
+    }
+}
+
+/// Widened to `i64` across chunks, as large hidden layers overflow `i32`
+fn dot(acc: &[i16], weights: &[i16]) -> i64 {
+    acc.chunks_exact(LANES)
+        .zip(weights.chunks_exact(LANES))
+        .map(|(a, w)| {
+            let a = Lanes::from_slice(a).simd_clamp(Lanes::splat(0), Lanes::splat(QA));
+            let w = Lanes::from_slice(w);
+            (a.cast::<i32>() * w.cast::<i32>()).reduce_sum() as i64
+        })
+        .sum()
+}
@@ -0,0 +1,174 @@
+use strum::VariantArray;
+
+use crate::{
+    arrays::ArrayBoard,
+    bits::{
+        BoardMask, Squares,
+        attackers::{
+            count_bishop_attackers, count_king_attackers, count_knight_attackers,
+            count_pawn_attackers, count_queen_attackers, count_rook_attackers,
+        },
+        board::BitBoard,
+        fills::{east_west_fill, file_fill},
+        jumps::KING_MOVES,
+    },
+    eval::PieceTable,
+    model::{BoardFile, ChessPiece, Color, Square},
+};
+
+/// How much each kind of piece attacking the king zone counts towards danger
+pub const KING_ATTACK_WEIGHTS: PieceTable<i32> = PieceTable {
+    pawn: 1,
+    knight: 2,
+    bishop: 2,
+    rook: 3,
+    queen: 5,
+    king: 0,
+};
+
+/// The state of one side's king; files are given as whole-file masks
+/// and only cover the king's own and adjacent files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KingSafety {
+    pub king: Square,
+    pub zone: BoardMask,
+    /// Enemy pieces of each kind attacking at least one zone square
+    pub attackers: PieceTable<u8>,
+    /// Attacks on zone squares, once per attacker and square
+    pub attacks: PieceTable<u8>,
+    pub shield: BoardMask,
+    pub storm: BoardMask,
+    pub open_files: BoardMask,
+    pub half_open_files: BoardMask,
+}
+
+impl BitBoard {
+    /// `None` if the side has no king
+    pub fn king_safety(&self, c: Color) -> Option<KingSafety> {
+        let (friendly, enemy) = self.active_passive(c);
+        let king = Squares(friendly.kings).next()?;
+        let zone = king.bit() | KING_MOVES.at(king);
+
+        let row = king.bit() | east_west_fill(king.bit());
+        let files = file_fill(row);
+
+        let occupied = friendly.total | enemy.total;
+        let mut attackers = PieceTable::<u8>::default();
+        let mut attacks = PieceTable::<u8>::default();
+        let mut counts = ArrayBoard::new(0i8);
+
+        for p in ChessPiece::VARIANTS {
+            for sq in Squares(enemy.mask(*p)) {
+                counts.zero();
+                match p {
+                    ChessPiece::Pawn => {
+                        count_pawn_attackers(sq.bit(), c.opposite(), 1, &mut counts)
+                    }
+                    ChessPiece::Knight => count_knight_attackers(sq.bit(), 1, &mut counts),
+                    ChessPiece::Bishop => {
+                        count_bishop_attackers(sq.bit(), occupied, 1, &mut counts)
+                    }
+                    ChessPiece::Rook => count_rook_attackers(sq.bit(), occupied, 1, &mut counts),
+                    ChessPiece::Queen => count_queen_attackers(sq.bit(), occupied, 1, &mut counts),
+                    ChessPiece::King => count_king_attackers(sq.bit(), 1, &mut counts),
+                }
+
+                let n = Squares(zone).map(|sq| counts.at(sq) as u8).sum::<u8>();
+                if n > 0 {
+                    *attackers.get_mut(*p) += 1;
+                    *attacks.get_mut(*p) += n;
+                }
+            }
+        }
+
+        Some(KingSafety {
+            king,
+            zone,
+            attackers,
+            attacks,
+            shield: friendly.pawns & ahead(row, c, 2),
+            storm: enemy.pawns & ahead(row, c, 4),
+            open_files: self.open_files() & files,
+            half_open_files: self.half_open_files(c) & files,
+        })
+    }
+}
+
+/// The `ranks` ranks in front of `mask` as seen by `c`
+fn ahead(mask: BoardMask, c: Color, ranks: u32) -> BoardMask {
+    let mut res = BoardMask::MIN;
+    let mut front = mask;
+    for _ in 0..ranks {
+        front = match c {
+            Color::White => front << 8,
+            Color::Black => front >> 8,
+        };
+        res |= front;
+    }
+    res
+}
+
+impl KingSafety {
+    /// Weighted attacks on the king zone, only counted once
+    /// at least two enemy pieces take part
+    pub fn danger(&self, weights: &PieceTable<i32>) -> i32 {
+        let attackers = ChessPiece::VARIANTS
+            .iter()
+            .map(|p| *self.attackers.get(*p) as i32)
+            .sum::<i32>();
+
+        if attackers < 2 {
+            return 0;
+        }
+
+        ChessPiece::VARIANTS
+            .iter()
+            .map(|p| *self.attacks.get(*p) as i32 * weights.get(*p))
+            .sum()
+    }
+
+    /// One line per notable feature, for human consumption
+    pub fn commentary(&self) -> Vec<String> {
+        let mut res = vec![];
+
+        let attackers = ChessPiece::VARIANTS
+            .iter()
+            .filter(|p| *self.attackers.get(**p) > 0)
+            .map(|p| format!("{}x{}", self.attackers.get(*p), p.letter()))
+            .collect::<Vec<_>>();
+        if !attackers.is_empty() {
+            res.push(format!(
+                "king on {} is attacked by {}",
+                self.king.to_str(),
+                attackers.join(", ")
+            ));
+        }
+
+        match self.shield.count_ones() {
+            0 => res.push("no pawn shield".to_string()),
+            n => res.push(format!("pawn shield of {n}")),
+        }
+
+        if self.storm != 0 {
+            res.push(format!("pawn storm of {}", self.storm.count_ones()));
+        }
+
+        if self.open_files != 0 {
+            res.push(format!("open files {}", files(self.open_files)));
+        }
+
+        if self.half_open_files != 0 {
+            res.push(format!("half-open files {}", files(self.half_open_files)));
+        }
+
+        res
+    }
+}
+
+fn files(mask: BoardMask) -> String {
+    BoardFile::VARIANTS
+        .iter()
+        .filter(|f| f.mask() & mask != 0)
+        .map(|f| f.letter())
+        .collect()
+}
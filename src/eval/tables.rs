@@ -0,0 +1,164 @@
+use crate::eval::{EvalParams, PieceTable, Weights};
+
+pub const DEFAULT_PARAMS: EvalParams = EvalParams {
+    middlegame: Weights {
+        material: PieceTable {
+            pawn: 82,
+            knight: 337,
+            bishop: 365,
+            rook: 477,
+            queen: 1025,
+            king: 0,
+        },
+        mobility: PieceTable {
+            pawn: 0,
+            knight: 4,
+            bishop: 5,
+            rook: 2,
+            queen: 1,
+            king: 0,
+        },
+        squares: PieceTable {
+            pawn: [
+                [0, 0, 0, 0, 0, 0, 0, 0],
+                [50, 50, 50, 50, 50, 50, 50, 50],
+                [10, 10, 20, 30, 30, 20, 10, 10],
+                [5, 5, 10, 25, 25, 10, 5, 5],
+                [0, 0, 0, 20, 20, 0, 0, 0],
+                [5, -5, -10, 0, 0, -10, -5, 5],
+                [5, 10, 10, -20, -20, 10, 10, 5],
+                [0, 0, 0, 0, 0, 0, 0, 0],
+            ],
+            knight: [
+                [-50, -40, -30, -30, -30, -30, -40, -50],
+                [-40, -20, 0, 0, 0, 0, -20, -40],
+                [-30, 0, 10, 15, 15, 10, 0, -30],
+                [-30, 5, 15, 20, 20, 15, 5, -30],
+                [-30, 0, 15, 20, 20, 15, 0, -30],
+                [-30, 5, 10, 15, 15, 10, 5, -30],
+                [-40, -20, 0, 5, 5, 0, -20, -40],
+                [-50, -40, -30, -30, -30, -30, -40, -50],
+            ],
+            bishop: [
+                [-20, -10, -10, -10, -10, -10, -10, -20],
+                [-10, 0, 0, 0, 0, 0, 0, -10],
+                [-10, 0, 5, 10, 10, 5, 0, -10],
+                [-10, 5, 5, 10, 10, 5, 5, -10],
+                [-10, 0, 10, 10, 10, 10, 0, -10],
+                [-10, 10, 10, 10, 10, 10, 10, -10],
+                [-10, 5, 0, 0, 0, 0, 5, -10],
+                [-20, -10, -10, -10, -10, -10, -10, -20],
+            ],
+            rook: [
+                [0, 0, 0, 0, 0, 0, 0, 0],
+                [5, 10, 10, 10, 10, 10, 10, 5],
+                [-5, 0, 0, 0, 0, 0, 0, -5],
+                [-5, 0, 0, 0, 0, 0, 0, -5],
+                [-5, 0, 0, 0, 0, 0, 0, -5],
+                [-5, 0, 0, 0, 0, 0, 0, -5],
+                [-5, 0, 0, 0, 0, 0, 0, -5],
+                [0, 0, 0, 5, 5, 0, 0, 0],
+            ],
+            queen: [
+                [-20, -10, -10, -5, -5, -10, -10, -20],
+                [-10, 0, 0, 0, 0, 0, 0, -10],
+                [-10, 0, 5, 5, 5, 5, 0, -10],
+                [-5, 0, 5, 5, 5, 5, 0, -5],
+                [0, 0, 5, 5, 5, 5, 0, -5],
+                [-10, 5, 5, 5, 5, 5, 0, -10],
+                [-10, 0, 5, 0, 0, 0, 0, -10],
+                [-20, -10, -10, -5, -5, -10, -10, -20],
+            ],
+            king: [
+                [-30, -40, -40, -50, -50, -40, -40, -30],
+                [-30, -40, -40, -50, -50, -40, -40, -30],
+                [-30, -40, -40, -50, -50, -40, -40, -30],
+                [-30, -40, -40, -50, -50, -40, -40, -30],
+                [-20, -30, -30, -40, -40, -30, -30, -20],
+                [-10, -20, -20, -20, -20, -20, -20, -10],
+                [20, 20, 0, 0, 0, 0, 20, 20],
+                [20, 30, 10, 0, 0, 10, 30, 20],
+            ],
+        },
+    },
+    endgame: Weights {
+        material: PieceTable {
+            pawn: 94,
+            knight: 281,
+            bishop: 297,
+            rook: 512,
+            queen: 936,
+            king: 0,
+        },
+        mobility: PieceTable {
+            pawn: 0,
+            knight: 4,
+            bishop: 5,
+            rook: 4,
+            queen: 2,
+            king: 0,
+        },
+        squares: PieceTable {
+            pawn: [
+                [0, 0, 0, 0, 0, 0, 0, 0],
+                [80, 80, 80, 80, 80, 80, 80, 80],
+                [50, 50, 50, 50, 50, 50, 50, 50],
+                [30, 30, 30, 30, 30, 30, 30, 30],
+                [15, 15, 15, 15, 15, 15, 15, 15],
+                [5, 5, 5, 5, 5, 5, 5, 5],
+                [0, 0, 0, 0, 0, 0, 0, 0],
+                [0, 0, 0, 0, 0, 0, 0, 0],
+            ],
+            knight: [
+                [-50, -40, -30, -30, -30, -30, -40, -50],
+                [-40, -20, 0, 0, 0, 0, -20, -40],
+                [-30, 0, 10, 15, 15, 10, 0, -30],
+                [-30, 5, 15, 20, 20, 15, 5, -30],
+                [-30, 0, 15, 20, 20, 15, 0, -30],
+                [-30, 5, 10, 15, 15, 10, 5, -30],
+                [-40, -20, 0, 5, 5, 0, -20, -40],
+                [-50, -40, -30, -30, -30, -30, -40, -50],
+            ],
+            bishop: [
+                [-20, -10, -10, -10, -10, -10, -10, -20],
+                [-10, 0, 0, 0, 0, 0, 0, -10],
+                [-10, 0, 5, 10, 10, 5, 0, -10],
+                [-10, 5, 5, 10, 10, 5, 5, -10],
+                [-10, 0, 10, 10, 10, 10, 0, -10],
+                [-10, 10, 10, 10, 10, 10, 10, -10],
+                [-10, 5, 0, 0, 0, 0, 5, -10],
+                [-20, -10, -10, -10, -10, -10, -10, -20],
+            ],
+            rook: [
+                [0, 0, 0, 0, 0, 0, 0, 0],
+                [5, 5, 5, 5, 5, 5, 5, 5],
+                [0, 0, 0, 0, 0, 0, 0, 0],
+                [0, 0, 0, 0, 0, 0, 0, 0],
+                [0, 0, 0, 0, 0, 0, 0, 0],
+                [0, 0, 0, 0, 0, 0, 0, 0],
+                [0, 0, 0, 0, 0, 0, 0, 0],
+                [0, 0, 0, 0, 0, 0, 0, 0],
+            ],
+            queen: [
+                [-20, -10, -10, -5, -5, -10, -10, -20],
+                [-10, 0, 0, 0, 0, 0, 0, -10],
+                [-10, 0, 5, 5, 5, 5, 0, -10],
+                [-5, 0, 5, 5, 5, 5, 0, -5],
+                [-5, 0, 5, 5, 5, 5, 0, -5],
+                [-10, 0, 5, 5, 5, 5, 0, -10],
+                [-10, 0, 0, 0, 0, 0, 0, -10],
+                [-20, -10, -10, -5, -5, -10, -10, -20],
+            ],
+            king: [
+                [-50, -40, -30, -20, -20, -30, -40, -50],
+                [-30, -20, -10, 0, 0, -10, -20, -30],
+                [-30, -10, 20, 30, 30, 20, -10, -30],
+                [-30, -10, 30, 40, 40, 30, -10, -30],
+                [-30, -10, 30, 40, 40, 30, -10, -30],
+                [-30, -10, 20, 30, 30, 20, -10, -30],
+                [-30, -30, 0, 0, 0, 0, -30, -30],
+                [-50, -30, -30, -30, -30, -30, -30, -50],
+            ],
+        },
+    },
+};
@@ -0,0 +1,299 @@
+use std::sync::Arc;
+
+use rand::seq::IndexedRandom;
+
+use crate::{
+    bits::board::BitBoard,
+    eval::{
+        EvalParams, MAX_PHASE, PieceTable,
+        nnue::{Network, Nnue, QA, QB, SCALE},
+        safety::KING_ATTACK_WEIGHTS,
+        tuning::{Tuner, TuningPosition, extract_positions, load_positions},
+    },
+    fuzzing::pi_rng,
+    model::{BoardFile, Color, ColoredChessPiece, Square, castling::CastlingRights},
+    notation::{fen::parse_fen, pgn::load_pgn_file},
+};
+
+#[cfg(test)]
+fn mirror(board: &BitBoard) -> BitBoard {
+    let orig = board.render();
+    let mut res = orig.clone();
+    for (sq, p) in &orig {
+        res.set(
+            sq.swap(),
+            p.map(|p| ColoredChessPiece::new(p.color().opposite(), p.piece())),
+        );
+    }
+
+    BitBoard::new(
+        &res,
+        board.metadata.to_move.opposite(),
+        board.metadata.turn,
+        board.metadata.halfmove_clock,
+        CastlingRights::nil(),
+        board.metadata.en_passant.map(|sq| sq.swap()),
+        board.metadata.castling_details,
+    )
+}
+
+#[test]
+fn eval_startpos_balanced() {
+    let board = BitBoard::startpos();
+    assert_eq!(board.evaluate(), 0);
+    assert_eq!(EvalParams::default().evaluation(&board).phase, MAX_PHASE);
+}
+
+#[test]
+fn eval_mirror_symmetry() {
+    for fen in [
+        "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w - - 2 3",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w - - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 b - - 0 1",
+        "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1",
+    ] {
+        let board = parse_fen(fen).unwrap();
+        assert_eq!(board.evaluate(), mirror(&board).evaluate(), "{fen}");
+    }
+}
+
+#[test]
+fn eval_side_to_move_relative() {
+    let white = parse_fen("4k3/8/8/8/8/8/8/3QK3 w - - 0 1").unwrap();
+    let black = parse_fen("4k3/8/8/8/8/8/8/3QK3 b - - 0 1").unwrap();
+
+    assert!(white.evaluate() > 800);
+    assert_eq!(white.evaluate(), -black.evaluate());
+}
+
+#[test]
+fn eval_phase_tapering() {
+    let params = EvalParams::default();
+    let endgame = parse_fen("4k3/pppp4/8/8/8/8/PPPP4/4K3 w - - 0 1").unwrap();
+    let eval = params.evaluation(&endgame);
+
+    assert_eq!(eval.phase, 0);
+    assert_eq!(eval.taper(), eval.endgame);
+
+    let advanced = parse_fen("4k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+    let home = parse_fen("4k3/8/8/8/8/8/P7/4K3 w - - 0 1").unwrap();
+    assert!(advanced.evaluate() > home.evaluate());
+}
+
+#[test]
+fn king_safety() {
+    let board = parse_fen("6k1/8/8/7p/7q/5n2/5PP1/6K1 w - - 0 1").unwrap();
+
+    let white = board.king_safety(Color::White).unwrap();
+    assert_eq!(white.king, Square::g1);
+    assert_eq!(
+        white.attackers,
+        PieceTable {
+            knight: 1,
+            queen: 1,
+            ..Default::default()
+        }
+    );
+    assert_eq!(
+        white.attacks,
+        PieceTable {
+            knight: 2,
+            queen: 3,
+            ..Default::default()
+        }
+    );
+    assert_eq!(white.shield, Square::f2.bit() | Square::g2.bit());
+    assert_eq!(white.storm, Square::h5.bit());
+    assert_eq!(white.open_files, 0);
+    assert_eq!(white.half_open_files, BoardFile::H.mask());
+    assert_eq!(white.danger(&KING_ATTACK_WEIGHTS), 19);
+    assert_eq!(
+        white.commentary(),
+        vec![
+            "king on g1 is attacked by 1xN, 1xQ",
+            "pawn shield of 2",
+            "pawn storm of 1",
+            "half-open files h",
+        ]
+    );
+
+    let black = board.king_safety(Color::Black).unwrap();
+    assert_eq!(black.zone.count_ones(), 6);
+    assert_eq!(black.attackers, PieceTable::default());
+    assert_eq!(black.danger(&KING_ATTACK_WEIGHTS), 0);
+    assert_eq!(
+        black.commentary(),
+        vec!["no pawn shield", "half-open files fg"]
+    );
+
+    assert_eq!(
+        parse_fen("8/8/8/8/8/8/8/4K3 w - - 0 1")
+            .unwrap()
+            .king_safety(Color::Black),
+        None
+    );
+}
+
+#[test]
+fn eval_params_toml_roundtrip() {
+    let params = EvalParams::default();
+    assert_eq!(EvalParams::from_toml(&params.to_toml()), Ok(params.clone()));
+    assert_eq!(EvalParams::from_vector(&params.to_vector()), params);
+    assert!(EvalParams::from_toml("middlegame = 3").is_err());
+}
+
+#[test]
+fn tuning_features_match_eval() {
+    let params = EvalParams::default();
+    let vector = params.to_vector();
+    for fen in [
+        "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w - - 2 3",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w - - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 b - - 0 1",
+    ] {
+        let board = parse_fen(fen).unwrap();
+        let expected = params.evaluation(&board).taper() as f64;
+        let actual = TuningPosition::new(board, 0.5).evaluate(&vector);
+        assert!(
+            (expected - actual).abs() <= 1.0,
+            "{fen}: {expected} {actual}"
+        );
+    }
+}
+
+#[test]
+fn tuning_reduces_loss() {
+    let pgns = load_pgn_file(
+        "[Result \"1-0\"]\n\n1. e4 e5 2. Bc4 Nc6 3. Qh5 Nf6 4. Qxf7# 1-0\n\n\
+         [Result \"0-1\"]\n\n1. f3 e5 2. g4 Qh4# 0-1\n\n\
+         [Result \"*\"]\n\n1. d4 d5 *\n\n",
+    );
+    assert_eq!(pgns.len(), 3);
+
+    let positions = extract_positions(&pgns);
+    assert!(!positions.is_empty());
+    assert!(
+        positions
+            .iter()
+            .all(|p| p.result != 0.5 && p.board.is_quiet())
+    );
+
+    let mut tuner = Tuner::new(positions, &EvalParams::default());
+    let before = tuner.loss();
+    let tuned = tuner.tune(10);
+    assert!(tuner.loss() < before);
+    assert_ne!(tuned, EvalParams::default());
+}
+
+#[test]
+fn tuning_load_positions() {
+    let dir = std::env::temp_dir().join(format!("mintymacks-tuning-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let white = dir.join("white.pgn");
+    std::fs::write(
+        &white,
+        "[Result \"1-0\"]\n\n1. e4 e5 2. Bc4 Nc6 3. Qh5 Nf6 4. Qxf7# 1-0\n\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("black.pgn"),
+        "[Result \"0-1\"]\n\n1. f3 e5 2. g4 Qh4# 0-1\n\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("notes.txt"),
+        "[Result \"1/2-1/2\"]\n\n1. d4 d5 1/2-1/2\n\n",
+    )
+    .unwrap();
+
+    let file = load_positions(&white);
+    let all = load_positions(&dir);
+    let missing = load_positions(&dir.join("missing.pgn"));
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let file = file.unwrap();
+    assert!(!file.is_empty() && file.iter().all(|p| p.result == 1.0));
+
+    let all = all.unwrap();
+    assert!(all.len() > file.len());
+    assert!(all.iter().all(|p| p.result != 0.5));
+    assert!(all.iter().any(|p| p.result == 0.0));
+    assert!(missing.is_err());
+}
+
+#[cfg(test)]
+fn tiny_network() -> Arc<Network> {
+    Arc::new(Network::from_bytes(include_bytes!("../../tests/tiny.nnue")).unwrap())
+}
+
+#[test]
+fn nnue_load() {
+    let bytes = include_bytes!("../../tests/tiny.nnue");
+    let network = Network::from_bytes(bytes).unwrap();
+
+    assert_eq!(network.hidden, 16);
+    assert_eq!(network.to_bytes(), bytes);
+    assert!(Network::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    assert!(Network::from_bytes(&[bytes.as_slice(), &[0]].concat()).is_err());
+    assert!(Network::from_bytes(b"NOPE").is_err());
+}
+
+#[test]
+fn nnue_evaluate() {
+    let board = BitBoard::startpos();
+    let nnue = Nnue::new(tiny_network(), &board);
+    assert_eq!(nnue.evaluate(board.metadata.to_move), 0);
+
+    for (fen, score) in [
+        ("4k3/8/8/8/8/8/8/3QK3 w - - 0 1", 900),
+        ("4k3/8/8/8/8/8/8/3QK3 b - - 0 1", -900),
+        ("4k3/pp6/8/8/8/8/8/3RK3 w - - 0 1", 300),
+    ] {
+        let board = parse_fen(fen).unwrap();
+        let nnue = Nnue::new(tiny_network(), &board);
+        assert_eq!(nnue.evaluate(board.metadata.to_move), score, "{fen}");
+    }
+}
+
+#[test]
+fn nnue_incremental_updates() {
+    let mut rng = pi_rng();
+    let mut board =
+        parse_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+    let mut nnue = Nnue::new(tiny_network(), &board);
+    let start = nnue.clone();
+
+    for _ in 0..20 {
+        let mut played = vec![];
+        let mut moves = vec![];
+        for _ in 0..30 {
+            moves.clear();
+            board.moves(&mut moves);
+            let Some(mv) = moves.choose(&mut rng) else {
+                break;
+            };
+            board.apply(*mv);
+            nnue.delta(*mv, board.metadata.castling_details, true);
+            played.push(*mv);
+
+            assert_eq!(nnue, Nnue::new(tiny_network(), &board));
+        }
+
+        for mv in played.into_iter().rev() {
+            board.unapply(mv);
+            nnue.delta(mv, board.metadata.castling_details, false);
+        }
+        assert_eq!(nnue, start);
+    }
+}
+
+#[test]
+fn nnue_wide_output() {
+    let mut network = (*tiny_network()).clone();
+    network.output_bias = i32::MAX;
+    let nnue = Nnue::new(Arc::new(network), &BitBoard::startpos());
+
+    // The balanced start position leaves just the bias, without overflowing
+    let expected = i32::MAX as i64 * SCALE as i64 / (QA as i64 * QB as i64);
+    assert!((nnue.evaluate(Color::White) as i64 - expected).abs() <= 1);
+}
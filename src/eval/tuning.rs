@@ -0,0 +1,281 @@
+use std::path::Path;
+
+use strum::VariantArray;
+
+use crate::{
+    bits::{Squares, attacks::pawn_attacks, board::BitBoard},
+    eval::{EvalParams, MAX_PHASE, Weights, mobility},
+    game::GameState,
+    model::{ChessPiece, Color, Victory},
+    notation::pgn::{PGN, load_pgn_file},
+};
+
+/// Tunable values per game phase: material and mobility for
+/// each piece, followed by the piece-square tables
+const PHASE_PARAMS: usize = 6 + 6 + 6 * 64;
+pub const PARAMS: usize = 2 * PHASE_PARAMS;
+
+const MATERIAL: usize = 0;
+const MOBILITY: usize = 6;
+const SQUARES: usize = 12;
+
+/// A quiet position and the white-relative outcome of its game,
+/// with the evaluation reduced to its linear coefficients
+#[derive(Debug, Clone)]
+pub struct TuningPosition {
+    pub board: BitBoard,
+    pub result: f64,
+    pub features: Vec<(usize, f64)>,
+}
+
+impl TuningPosition {
+    pub fn new(board: BitBoard, result: f64) -> Self {
+        Self {
+            features: features(&board),
+            board,
+            result,
+        }
+    }
+
+    /// White-relative evaluation under `params` as laid out by `EvalParams::to_vector`
+    pub fn evaluate(&self, params: &[f64]) -> f64 {
+        self.features.iter().map(|(i, x)| params[*i] * x).sum()
+    }
+}
+
+/// Positions from every decided game where the side to move
+/// is not in check and has no winning capture
+pub fn extract_positions(pgns: &[PGN]) -> Vec<TuningPosition> {
+    let mut res = vec![];
+
+    for pgn in pgns {
+        let result = match pgn.end {
+            Some(Victory::WhiteWins(_)) => 1.0,
+            Some(Victory::BlackWins(_)) => 0.0,
+            Some(Victory::Draw(_)) => 0.5,
+            None => continue,
+        };
+
+        let Ok(game) = GameState::from_pgn(pgn) else {
+            continue;
+        };
+
+        let mut board = game
+            .start
+            .clone()
+            .map(|b| *b)
+            .unwrap_or_else(BitBoard::startpos);
+
+        for fm in &game.move_sequence {
+            if board.is_quiet() {
+                res.push(TuningPosition::new(board.clone(), result));
+            }
+            board.apply(fm.chessmove);
+        }
+
+        if board.is_quiet() {
+            res.push(TuningPosition::new(board, result));
+        }
+    }
+
+    res
+}
+
+/// Tuning positions from a PGN file, or from every `.pgn` file in a directory
+pub fn load_positions(path: &Path) -> Result<Vec<TuningPosition>, String> {
+    let files = if path.is_dir() {
+        let mut files = vec![];
+        for entry in std::fs::read_dir(path).map_err(|e| format!("{}: {e}", path.display()))? {
+            let file = entry.map_err(|e| e.to_string())?.path();
+            if file.extension().is_some_and(|ext| ext == "pgn") {
+                files.push(file);
+            }
+        }
+        files.sort();
+        files
+    } else {
+        vec![path.to_path_buf()]
+    };
+
+    let mut pgns = vec![];
+    for file in files {
+        let text =
+            std::fs::read_to_string(&file).map_err(|e| format!("{}: {e}", file.display()))?;
+        pgns.extend(load_pgn_file(&text));
+    }
+
+    Ok(extract_positions(&pgns))
+}
+
+impl BitBoard {
+    pub fn is_quiet(&self) -> bool {
+        if self.in_check() {
+            return false;
+        }
+
+        let mut tactical = vec![];
+        self.tactical_moves(false, &mut tactical);
+        tactical.iter().all(|mv| self.see(*mv) <= 0)
+    }
+}
+
+fn features(board: &BitBoard) -> Vec<(usize, f64)> {
+    let mut res = vec![];
+    let phase = MAX_PHASE.min(board.white.phase() + board.black.phase()) as f64;
+    let tapers = [phase / MAX_PHASE as f64, 1.0 - phase / MAX_PHASE as f64];
+    let occupied = board.white.total | board.black.total;
+
+    for (c, sign) in [(Color::White, 1.0), (Color::Black, -1.0)] {
+        let (friendly, enemy) = board.active_passive(c);
+        let safe = !friendly.total & !pawn_attacks(enemy.pawns, c.opposite());
+
+        for p in ChessPiece::VARIANTS {
+            let ix = *p as usize - 1;
+            for sq in Squares(friendly.mask(*p)) {
+                let moves = (mobility(*p, sq, occupied) & safe).count_ones() as f64;
+                let (file, rank) = sq.file_rank();
+                let row = match c {
+                    Color::White => 7 - rank.ix(),
+                    Color::Black => rank.ix(),
+                } as usize;
+
+                for (phase, taper) in tapers.iter().enumerate() {
+                    let base = phase * PHASE_PARAMS;
+                    let x = sign * taper;
+                    res.push((base + MATERIAL + ix, x));
+                    res.push((base + MOBILITY + ix, x * moves));
+                    res.push((base + SQUARES + ix * 64 + row * 8 + file.ix() as usize, x));
+                }
+            }
+        }
+    }
+
+    res
+}
+
+/// Expected score for white given a white-relative centipawn evaluation
+pub fn win_probability(eval: f64, scale: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-scale * eval / 400.0))
+}
+
+pub struct Tuner {
+    pub positions: Vec<TuningPosition>,
+    pub params: Vec<f64>,
+    pub scale: f64 = 1.0,
+    pub learning_rate: f64 = 1000.0,
+}
+
+impl Tuner {
+    pub fn new(positions: Vec<TuningPosition>, start: &EvalParams) -> Self {
+        Self {
+            positions,
+            params: start.to_vector(),
+            ..
+        }
+    }
+
+    /// Mean cross-entropy between predicted and actual results
+    pub fn loss(&self) -> f64 {
+        let n = self.positions.len().max(1) as f64;
+        self.positions
+            .iter()
+            .map(|pos| {
+                let p = win_probability(pos.evaluate(&self.params), self.scale)
+                    .clamp(1e-12, 1.0 - 1e-12);
+                -(pos.result * p.ln() + (1.0 - pos.result) * (1.0 - p).ln())
+            })
+            .sum::<f64>()
+            / n
+    }
+
+    /// One step of full-batch gradient descent, returning the loss before it
+    pub fn step(&mut self) -> f64 {
+        let n = self.positions.len().max(1) as f64;
+        let dp = self.scale * 10f64.ln() / 400.0;
+        let mut gradient = vec![0.0; PARAMS];
+
+        for pos in &self.positions {
+            let p = win_probability(pos.evaluate(&self.params), self.scale);
+            let error = (p - pos.result) * dp / n;
+            for (i, x) in &pos.features {
+                gradient[*i] += error * x;
+            }
+        }
+
+        let loss = self.loss();
+        for (param, g) in self.params.iter_mut().zip(gradient) {
+            *param -= self.learning_rate * g;
+        }
+        loss
+    }
+
+    pub fn tune(&mut self, iterations: usize) -> EvalParams {
+        for _ in 0..iterations {
+            self.step();
+        }
+        self.result()
+    }
+
+    pub fn result(&self) -> EvalParams {
+        EvalParams::from_vector(&self.params)
+    }
+}
+
+impl EvalParams {
+    pub fn to_vector(&self) -> Vec<f64> {
+        let mut res = Vec::with_capacity(PARAMS);
+        for w in [&self.middlegame, &self.endgame] {
+            for p in ChessPiece::VARIANTS {
+                res.push(*w.material.get(*p) as f64);
+            }
+            for p in ChessPiece::VARIANTS {
+                res.push(*w.mobility.get(*p) as f64);
+            }
+            for p in ChessPiece::VARIANTS {
+                res.extend(w.squares.get(*p).iter().flatten().map(|x| *x as f64));
+            }
+        }
+        res
+    }
+
+    pub fn from_vector(params: &[f64]) -> Self {
+        let mut res = EvalParams::default();
+        for (w, params) in [&mut res.middlegame, &mut res.endgame]
+            .into_iter()
+            .zip(params.chunks_exact(PHASE_PARAMS))
+        {
+            w.set_vector(params);
+        }
+        res
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("Unable to render TOML")
+    }
+
+    pub fn from_toml(s: &str) -> Result<Self, String> {
+        toml::from_str(s).map_err(|e| e.to_string())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        Self::from_toml(&std::fs::read_to_string(path).map_err(|e| e.to_string())?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        std::fs::write(path, self.to_toml()).map_err(|e| e.to_string())
+    }
+}
+
+impl Weights {
+    fn set_vector(&mut self, params: &[f64]) {
+        let round = |x: f64| x.round().clamp(i16::MIN as f64, i16::MAX as f64) as i16;
+        for p in ChessPiece::VARIANTS {
+            let ix = *p as usize - 1;
+            *self.material.get_mut(*p) = round(params[MATERIAL + ix]);
+            *self.mobility.get_mut(*p) = round(params[MOBILITY + ix]);
+            for (i, x) in self.squares.get_mut(*p).iter_mut().flatten().enumerate() {
+                *x = round(params[SQUARES + ix * 64 + i]);
+            }
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    time::Duration,
+};
+
+use crate::{
+    bits::board::BitBoard,
+    engine::EngineHandle,
+    fuzzing::engine_perft,
+    notation::{LongAlg, fen::render_fen},
+};
+
+/// The first position where our perft and a reference engine disagree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// Moves leading from the starting position to `fen`
+    pub path: Vec<LongAlg>,
+    pub fen: String,
+    pub depth: usize,
+    pub ours: usize,
+    pub theirs: usize,
+    /// Moves only the reference engine generates here
+    pub missing: Vec<LongAlg>,
+    /// Moves only we generate here
+    pub extra: Vec<LongAlg>,
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let show = |mvs: &[LongAlg]| {
+            mvs.iter()
+                .map(|(pmv, pr)| pmv.longalg(*pr))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+
+        writeln!(f, "Position: {}", self.fen)?;
+        writeln!(f, "Reached by: {}", show(&self.path))?;
+        writeln!(
+            f,
+            "Perft {}: {} nodes, reference {}",
+            self.depth, self.ours, self.theirs
+        )?;
+        writeln!(f, "Missing moves: {}", show(&self.missing))?;
+        write!(f, "Extra moves: {}", show(&self.extra))
+    }
+}
+
+/// Compares `enumerate` with the `go perft` of a reference engine, descending
+/// into the first root move whose counts differ until the move lists do,
+/// or `None` if the counts agree
+pub async fn find_divergence(
+    engine: &mut EngineHandle,
+    board: &BitBoard,
+    depth: usize,
+    timeout: Duration,
+) -> tokio::io::Result<Option<Divergence>> {
+    let mut board = board.clone();
+    let mut path = vec![];
+    let mut depth = depth;
+
+    while depth > 0 {
+        let ours = board.enumerate(depth).moves;
+        let theirs = engine_perft(engine, Some(&board), &[], depth, timeout).await?;
+
+        let missing = theirs
+            .keys()
+            .filter(|mv| !ours.contains_key(mv))
+            .copied()
+            .collect::<Vec<_>>();
+        let extra = ours
+            .keys()
+            .filter(|mv| !theirs.contains_key(mv))
+            .copied()
+            .collect::<Vec<_>>();
+
+        let differing = ours
+            .iter()
+            .find(|(mv, n)| theirs.get(mv).is_some_and(|m| m != *n))
+            .map(|(mv, _)| *mv);
+
+        let agree = missing.is_empty() && extra.is_empty();
+        if let Some(next) = differing
+            && agree
+            && depth > 1
+        {
+            let mut moves = vec![];
+            board.moves(&mut moves);
+            let mv = moves
+                .into_iter()
+                .find(|mv| mv.simplify() == next)
+                .expect("Perft move is legal");
+
+            board.apply(mv);
+            path.push(next);
+            depth -= 1;
+            continue;
+        }
+
+        // Agreeing below a disagreement means the reference treats this
+        // position differently from the moves leading to it
+        if agree && differing.is_none() && path.is_empty() {
+            return Ok(None);
+        }
+
+        return Ok(Some(Divergence {
+            path,
+            fen: render_fen(&board),
+            depth,
+            ours: ours.values().sum(),
+            theirs: theirs.values().sum(),
+            missing,
+            extra,
+        }));
+    }
+
+    Ok(None)
+}
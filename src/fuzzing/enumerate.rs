@@ -1,68 +1,104 @@
 use std::{
     collections::{BTreeMap, HashMap, btree_map, hash_map},
+    fmt::{self, Display, Formatter},
+    ops::AddAssign,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
     time::{Duration, Instant},
 };
 
 use rand::Rng;
 
 use crate::{
-    bits::board::BitBoard,
+    bits::{Squares, board::BitBoard},
     fuzzing::pi_rng_skip,
     model::{
         ChessPiece,
-        moves::{ChessMove, PseudoMove},
+        moves::{ChessMove, PseudoMove, SpecialMove},
     },
     println_async,
     utils::tree_map,
     zobrist::{
         ZOBRIST, ZobHash, ZobristBoard,
-        table::{ZobHasher, ZobHashing},
+        table::{PerftTable, ZobHasher, ZobHashing},
     },
 };
 
 impl BitBoard {
+    /// Plain perft on this thread, without a perft table
     pub fn enumerate(&self, depth: usize) -> EnumerationResult {
+        self.split_roots(depth, 1, None)
+    }
+
+    /// Splits the root moves between `threads` workers sharing one perft table
+    pub fn enumerate_parallel(&self, depth: usize, threads: usize) -> EnumerationResult {
+        let table = PerftTable::megabytes(1 << depth.min(8));
+        self.enumerate_with(depth, threads, &table)
+    }
+
+    pub fn enumerate_with(
+        &self,
+        depth: usize,
+        threads: usize,
+        table: &PerftTable,
+    ) -> EnumerationResult {
+        self.split_roots(depth, threads, Some(table))
+    }
+
+    fn split_roots(
+        &self,
+        depth: usize,
+        threads: usize,
+        table: Option<&PerftTable>,
+    ) -> EnumerationResult {
         if depth == 0 {
-            EnumerationResult {
+            return EnumerationResult {
                 time: Duration::ZERO,
                 depth,
                 moves: tree_map! {},
                 transpos: (0, 0),
-            }
-        } else {
-            self.clone().enumerate_mut(depth)
+            };
         }
-    }
-
-    fn enumerate_mut(&mut self, depth: usize) -> EnumerationResult {
-        let mut depth_hashes = vec![0; depth as usize];
-        pi_rng_skip(500).fill(&mut depth_hashes[..]);
 
         let now = Instant::now();
-        let mut moves = tree_map! {};
-        let mut zobrist = HashMap::with_hasher(ZobHashing);
-        // let mut zobrist = HashMap::with_capacity_and_hasher(10usize.pow(depth as u32), ZobHashing);
         let mut startmvs = vec![];
         self.moves(&mut startmvs);
+        let next = AtomicUsize::new(0);
 
-        let mut buf = Vec::with_capacity(startmvs.len());
+        let work = || {
+            let mut board = self.clone();
+            let mut buf = Vec::with_capacity(startmvs.len());
+            let mut res = vec![];
 
-        for mv in startmvs {
-            self.apply(mv);
-            buf.clear();
-            self.moves(&mut buf);
-            moves.insert(
-                mv.simplify(),
-                self.enum_nodes(&buf, depth - 1, &mut zobrist, &depth_hashes[..]),
-            );
-            self.unapply(mv);
-        }
+            while let Some(&mv) = startmvs.get(next.fetch_add(1, Ordering::Relaxed)) {
+                board.apply(mv);
+                buf.clear();
+                board.moves(&mut buf);
+                res.push((mv.simplify(), board.enum_nodes(&buf, depth - 1, table)));
+                board.unapply(mv);
+            }
+
+            res
+        };
+
+        let moves = if threads <= 1 {
+            work().into_iter().collect()
+        } else {
+            thread::scope(|s| {
+                let workers = (0..threads).map(|_| s.spawn(work)).collect::<Vec<_>>();
+
+                workers
+                    .into_iter()
+                    .flat_map(|w| w.join().expect("Perft worker panicked"))
+                    .collect()
+            })
+        };
 
         EnumerationResult {
             time: now.elapsed(),
             depth,
             moves,
-            transpos: (zobrist.len(), zobrist.capacity()),
+            transpos: table.map_or((0, 0), |t| (t.filled(), t.capacity())),
         }
     }
 
@@ -70,8 +106,7 @@ impl BitBoard {
         &mut self,
         moves: &[ChessMove],
         depth: usize,
-        zobrist: &mut HashMap<(ZobHash, ZobHash), usize, ZobHashing>,
-        depths: &[ZobHash],
+        table: Option<&PerftTable>,
     ) -> usize {
         let mut res = 0;
 
@@ -93,25 +128,202 @@ impl BitBoard {
                 let depth = depth - 1;
                 self.apply(mv);
 
-                // if let Some(n) = zobrist.get(&(self.metadata.hash, depths[depth])) {
-                //     res += n;
-                //     self.unapply(mv);
-                //     continue;
-                // }
+                // Counts one ply from the leaves are never stored
+                if depth > 1
+                    && let Some(n) = table.and_then(|t| t.get(self.metadata.hash, depth))
+                {
+                    res += n;
+                    self.unapply(mv);
+                    continue;
+                }
 
                 buf.clear();
                 self.moves(&mut buf);
-                res += self.enum_nodes(&buf, depth, zobrist, depths);
+                res += self.enum_nodes(&buf, depth, table);
                 self.unapply(mv);
             }
-        }
 
-        // zobrist.insert((self.metadata.hash, depths[depth]), res);
+            if let Some(table) = table {
+                table.insert(self.metadata.hash, depth, res);
+            }
+        }
 
         return res;
     }
 }
 
+impl BitBoard {
+    /// Perft that also sorts the leaves into the categories of the
+    /// chessprogramming perft tables, without the perft table
+    pub fn enumerate_detailed(&self, depth: usize) -> DetailedEnumeration {
+        let now = Instant::now();
+        let mut board = self.clone();
+        let mut moves = tree_map! {};
+
+        if depth > 0 {
+            let mut startmvs = vec![];
+            board.moves(&mut startmvs);
+
+            for mv in startmvs {
+                let mut stats = PerftStats::default();
+                board.detailed_nodes(mv, depth - 1, &mut stats);
+                moves.insert(mv.simplify(), stats);
+            }
+        }
+
+        DetailedEnumeration {
+            time: now.elapsed(),
+            depth,
+            moves,
+        }
+    }
+
+    fn detailed_nodes(&mut self, mv: ChessMove, depth: usize, stats: &mut PerftStats) {
+        self.apply(mv);
+        let mut buf = vec![];
+
+        if depth == 0 {
+            self.count_leaf(mv, stats);
+        } else {
+            self.moves(&mut buf);
+            for next in buf {
+                self.detailed_nodes(next, depth - 1, stats);
+            }
+        }
+
+        self.unapply(mv);
+    }
+
+    /// Classifies `mv`, which has just been played
+    fn count_leaf(&self, mv: ChessMove, stats: &mut PerftStats) {
+        stats.nodes += 1;
+
+        if let Some(cap) = mv.cap {
+            stats.captures += 1;
+            if cap != mv.pmv.to {
+                stats.en_passant += 1;
+            }
+        }
+
+        let mover = self.metadata.to_move.opposite();
+        let castling = match mv.spc {
+            Some(SpecialMove::Promotion(_)) => {
+                stats.promotions += 1;
+                None
+            }
+            Some(SpecialMove::CastlingWestward) => Some(self.metadata.castling_details.westward),
+            Some(SpecialMove::CastlingEastward) => Some(self.metadata.castling_details.eastward),
+            _ => None,
+        };
+
+        // The rook is the piece that can give check after castling
+        let moved = match castling {
+            Some(detail) => {
+                stats.castles += 1;
+                detail.reify(mover).rook_move.to.bit()
+            }
+            None => mv.pmv.to.bit(),
+        };
+
+        let (active, passive) = self.active_passive(self.metadata.to_move);
+        let occupied = active.total | passive.total;
+        let checkers = Squares(active.kings)
+            .map(|k| passive.attackers_to(mover, k, occupied))
+            .fold(0, |a, b| a | b);
+
+        if checkers == 0 {
+            return;
+        }
+
+        // The tables count a double check only as such, not as discovered too
+        stats.checks += 1;
+        if checkers.count_ones() > 1 {
+            stats.double_checks += 1;
+        } else if checkers & !moved != 0 {
+            stats.discovered_checks += 1;
+        }
+
+        let mut replies = vec![];
+        self.moves(&mut replies);
+        if replies.is_empty() {
+            stats.checkmates += 1;
+        }
+    }
+}
+
+/// Leaf counts by move category, as in the chessprogramming perft tables
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PerftStats {
+    pub nodes: usize,
+    pub captures: usize,
+    pub en_passant: usize,
+    pub castles: usize,
+    pub promotions: usize,
+    pub checks: usize,
+    pub discovered_checks: usize,
+    pub double_checks: usize,
+    pub checkmates: usize,
+}
+
+impl AddAssign for PerftStats {
+    fn add_assign(&mut self, rhs: Self) {
+        self.nodes += rhs.nodes;
+        self.captures += rhs.captures;
+        self.en_passant += rhs.en_passant;
+        self.castles += rhs.castles;
+        self.promotions += rhs.promotions;
+        self.checks += rhs.checks;
+        self.discovered_checks += rhs.discovered_checks;
+        self.double_checks += rhs.double_checks;
+        self.checkmates += rhs.checkmates;
+    }
+}
+
+impl Display for PerftStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} nodes, {} captures, {} e.p., {} castles, {} promotions, \
+             {} checks, {} discovered, {} double, {} checkmates",
+            self.nodes,
+            self.captures,
+            self.en_passant,
+            self.castles,
+            self.promotions,
+            self.checks,
+            self.discovered_checks,
+            self.double_checks,
+            self.checkmates
+        )
+    }
+}
+
+pub struct DetailedEnumeration {
+    pub time: Duration,
+    pub depth: usize,
+    pub moves: BTreeMap<(PseudoMove, Option<ChessPiece>), PerftStats>,
+}
+
+impl DetailedEnumeration {
+    pub fn total(&self) -> PerftStats {
+        let mut res = PerftStats::default();
+        for stats in self.moves.values() {
+            res += *stats;
+        }
+        res
+    }
+
+    pub fn print(&self) {
+        println!("Depth searched: {}", self.depth);
+        println!("Time elapsed: {} ms", self.time.as_millis());
+        println!("Total: {}", self.total());
+
+        for (k, v) in &self.moves {
+            println!("{}: {}", k.0.longalg(k.1), v);
+        }
+    }
+}
+
 pub struct EnumerationResult {
     pub time: Duration,
     pub depth: usize,
@@ -1,3 +1,4 @@
+pub mod divergence;
 pub mod enumerate;
 pub mod test;
 
@@ -48,6 +49,18 @@ pub async fn stockfish_perft(
     startpos: Option<&BitBoard>,
     moves: &[ChessMove],
     depth: usize,
+) -> tokio::io::Result<BTreeMap<(PseudoMove, Option<ChessPiece>), usize>> {
+    engine_perft(engine, startpos, moves, depth, Duration::from_millis(1000)).await
+}
+
+/// Per-move counts from any engine answering `go perft` like Stockfish,
+/// giving up on lines still missing after `timeout`
+pub async fn engine_perft(
+    engine: &mut EngineHandle,
+    startpos: Option<&BitBoard>,
+    moves: &[ChessMove],
+    depth: usize,
+    timeout: Duration,
 ) -> tokio::io::Result<BTreeMap<(PseudoMove, Option<ChessPiece>), usize>> {
     let mut res = tree_map! {};
 
@@ -74,7 +87,7 @@ pub async fn stockfish_perft(
                 },
                 _ => false,
             },
-            Duration::from_millis(1000),
+            timeout,
         )
         .await?;
 
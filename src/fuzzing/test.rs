@@ -1,7 +1,7 @@
 use std::{
-    collections::{HashMap, VecDeque},
+    collections::{BTreeMap, HashMap, VecDeque},
     io::Write,
-    path::Path,
+    path::{Path, PathBuf},
     time::{Duration, Instant},
 };
 
@@ -21,19 +21,20 @@ use crate::{
         show_mask,
     },
     deque,
-    fuzzing::{pi_rng, stockfish_perft},
+    fuzzing::{divergence::find_divergence, enumerate::PerftStats, pi_rng, stockfish_perft},
     model::{
         ChessPiece, Color, ColoredChessPiece, Square,
         castling::{CLASSIC_CASTLING, CastlingRights},
         moves::PseudoMove,
     },
     notation::{
-        fen::{self, parse_fen, parse_fen_board, render_fen_board},
+        LongAlg,
+        fen::{self, parse_fen, parse_fen_board, render_fen, render_fen_board},
         pgn::load_pgn_file,
         uci::{engine::UciEngine, gui::UciGui},
     },
     println_async,
-    zobrist::{self, ZOBRIST, ZobHash, ZobristBoard},
+    zobrist::{self, ZOBRIST, ZobHash, ZobristBoard, table::PerftTable},
 };
 
 #[test]
@@ -362,3 +363,204 @@ async fn fuzz_against_stockfish() {
     rng.next_u64();
     fuzz_stockfish_comparison(&mut engine, &mut rng, 1_000, 96, 3).await;
 }
+
+/// Standard perft positions with their node counts from depth 1
+#[cfg(test)]
+pub const PERFT_POSITIONS: &[(&str, &[usize])] = &[
+    (
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        &[20, 400, 8902, 197281],
+    ),
+    (
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        &[48, 2039, 97862],
+    ),
+    (
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        &[14, 191, 2812, 43238],
+    ),
+    (
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        &[6, 264, 9467, 422333],
+    ),
+    (
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        &[44, 1486, 62379],
+    ),
+    (
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+        &[46, 2079, 89890],
+    ),
+];
+
+#[test]
+fn perft_hashed_and_parallel() {
+    for (fen, counts) in PERFT_POSITIONS {
+        let board = parse_fen(fen).unwrap();
+        for (depth, count) in counts.iter().enumerate().map(|(i, n)| (i + 1, *n)) {
+            assert_eq!(board.enumerate(depth).total(), count, "{fen} at {depth}");
+            assert_eq!(
+                board.enumerate_parallel(depth, 4).total(),
+                count,
+                "{fen} at {depth} in parallel"
+            );
+        }
+    }
+}
+
+/// Takes minutes, run with `cargo test --release -- --ignored`
+#[test]
+#[ignore]
+fn perft_deep() {
+    let board = BitBoard::startpos();
+    assert_eq!(board.enumerate_parallel(7, 4).total(), 3_195_901_860);
+}
+
+#[test]
+fn perft_small_table() {
+    let board = parse_fen("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1").unwrap();
+    let table = PerftTable::megabytes(1);
+
+    let res = board.enumerate_with(5, 3, &table);
+    assert_eq!(res.total(), 674624);
+    assert!(res.transpos.0 > 0 && res.transpos.0 <= table.capacity());
+
+    // A second run starts from the counts the first left behind
+    assert_eq!(board.enumerate_with(5, 3, &table).total(), 674624);
+}
+
+#[test]
+fn perft_detailed_stats() {
+    #[rustfmt::skip]
+    let expected: &[(&str, usize, [usize; 9])] = &[
+        ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 4,
+            [197281, 1576, 0, 0, 0, 469, 0, 0, 8]),
+        ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", 3,
+            [97862, 17102, 45, 3162, 0, 993, 0, 0, 1]),
+        ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 5,
+            [674624, 52051, 1165, 0, 0, 52950, 1292, 3, 0]),
+        ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", 4,
+            [4085603, 757163, 1929, 128013, 15172, 25523, 42, 6, 43]),
+        ("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1", 2,
+            [264, 87, 0, 6, 48, 10, 0, 0, 0]),
+    ];
+
+    for (fen, depth, [nodes, captures, ep, castles, promotions, checks, disc, double, mates]) in
+        expected
+    {
+        let stats = parse_fen(fen).unwrap().enumerate_detailed(*depth).total();
+        assert_eq!(
+            stats,
+            PerftStats {
+                nodes: *nodes,
+                captures: *captures,
+                en_passant: *ep,
+                castles: *castles,
+                promotions: *promotions,
+                checks: *checks,
+                discovered_checks: *disc,
+                double_checks: *double,
+                checkmates: *mates,
+            },
+            "{fen} at {depth}"
+        );
+    }
+}
+
+/// Removes the mock engine's scratch directory once the test is done with it
+#[cfg(test)]
+struct MockDir(PathBuf);
+
+#[cfg(test)]
+impl Drop for MockDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A UCI engine script answering `go perft` with canned output per position
+#[cfg(test)]
+async fn mock_perft_engine(name: &str, responses: &[(String, String)]) -> (EngineHandle, MockDir) {
+    let dir = std::env::temp_dir().join(format!("mintymacks-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let guard = MockDir(dir.clone());
+
+    let mut script = "while read -r line; do\n  case \"$line\" in\n".to_string();
+    for (i, (fen, output)) in responses.iter().enumerate() {
+        std::fs::write(dir.join(format!("{i}.txt")), output).unwrap();
+        script += &format!("    \"position fen {fen}\"*) pos={i} ;;\n");
+    }
+    script += &format!("    \"go perft\"*) cat \"{}/$pos.txt\" ;;\n", dir.display());
+    script += "    quit) exit 0 ;;\n  esac\ndone\n";
+
+    let path = dir.join("mock.sh");
+    std::fs::write(&path, script).unwrap();
+    let engine = EngineHandle::open(Path::new("sh"), &[&path], false)
+        .await
+        .unwrap();
+    (engine, guard)
+}
+
+#[cfg(test)]
+fn perft_output(moves: &BTreeMap<LongAlg, usize>) -> String {
+    let mut res = String::new();
+    for ((pmv, pr), n) in moves {
+        res += &format!("{}: {}\n", pmv.longalg(*pr), n);
+    }
+    res + &format!("\nNodes searched: {}\n", moves.values().sum::<usize>())
+}
+
+#[tokio::test]
+async fn perft_divergence_bisection() {
+    let mv = |s: &str| PseudoMove::parse(s).unwrap();
+    let root = BitBoard::startpos();
+    let mut after_e4 = root.clone();
+    after_e4.apply_pseudomoves(&[mv("e2e4")]);
+    let mut after_d5 = after_e4.clone();
+    after_d5.apply_pseudomoves(&[mv("d7d5")]);
+
+    // The reference misses e4d5 and invents two moves, one node more in all
+    let mut root_counts = root.enumerate(3).moves;
+    *root_counts.get_mut(&mv("e2e4")).unwrap() += 1;
+    let mut e4_counts = after_e4.enumerate(2).moves;
+    *e4_counts.get_mut(&mv("d7d5")).unwrap() += 1;
+    let mut d5_counts = after_d5.enumerate(1).moves;
+    d5_counts.remove(&mv("e4d5"));
+    d5_counts.insert(mv("e4e6"), 1);
+    d5_counts.insert(mv("a2a5"), 1);
+
+    let (mut engine, _dir) = mock_perft_engine(
+        "perft-divergence",
+        &[
+            (render_fen(&root), perft_output(&root_counts)),
+            (render_fen(&after_e4), perft_output(&e4_counts)),
+            (render_fen(&after_d5), perft_output(&d5_counts)),
+        ],
+    )
+    .await;
+
+    let timeout = Duration::from_secs(10);
+    let divergence = find_divergence(&mut engine, &root, 3, timeout)
+        .await
+        .unwrap()
+        .expect("The mock disagrees");
+
+    assert_eq!(divergence.path, [mv("e2e4"), mv("d7d5")]);
+    assert_eq!(divergence.fen, render_fen(&after_d5));
+    assert_eq!(divergence.depth, 1);
+    assert_eq!(divergence.theirs, divergence.ours + 1);
+    assert_eq!(divergence.missing, [mv("a2a5"), mv("e4e6")]);
+    assert_eq!(divergence.extra, [mv("e4d5")]);
+
+    let (mut engine, _dir) = mock_perft_engine(
+        "perft-agreement",
+        &[(render_fen(&root), perft_output(&root.enumerate(2).moves))],
+    )
+    .await;
+    assert_eq!(
+        find_divergence(&mut engine, &root, 2, timeout)
+            .await
+            .unwrap(),
+        None
+    );
+}
@@ -36,7 +36,9 @@ use crate::{
 
 pub mod arrays;
 pub mod bits;
+pub mod bits2;
 pub mod engine;
+pub mod eval;
 pub mod fuzzing;
 pub mod model;
 #[macro_use]
@@ -44,6 +46,8 @@ pub mod notation;
 pub mod game;
 pub mod openings;
 pub mod profile;
+pub mod search;
+pub mod tablebase;
 pub mod utils;
 pub mod zobrist;
 
@@ -39,6 +39,11 @@ impl CastlingRights {
         }
     }
 
+    #[must_use]
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
     #[must_use]
     pub const fn move_king(self, c: Color) -> Self {
         Self(match c {
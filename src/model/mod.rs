@@ -361,6 +361,7 @@ pub enum DrawReason {
     Inactivity = 2,
     Insufficient = 3,
     Repetition = 4,
+    Adjudication = 5,
 }
 
 impl DrawReason {
@@ -371,6 +372,7 @@ impl DrawReason {
             Self::Inactivity => " {75-move rule}",
             Self::Insufficient => " {insufficient materiel}",
             Self::Repetition => " {repetition}",
+            Self::Adjudication => " {adjudication}",
         }
     }
 }
@@ -383,6 +385,7 @@ pub enum WinReason {
     Resign = 2,
     Time = 3,
     Forefeit = 4,
+    Adjudication = 5,
 }
 
 impl WinReason {
@@ -393,6 +396,7 @@ impl WinReason {
             Self::Resign => " {resignation}",
             Self::Time => " {time}",
             Self::Forefeit => " {forefeit}",
+            Self::Adjudication => " {adjudication}",
         }
     }
 }
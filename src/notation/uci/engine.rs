@@ -388,7 +388,8 @@ impl Uci for ScoreBound {
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ScoreString {
     Centipawns(i64),
-    MateIn(u64),
+    /// Moves, not plies, to mate; negative when the engine is getting mated
+    MateIn(i64),
 }
 
 impl Uci for ScoreString {
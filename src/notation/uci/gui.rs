@@ -194,6 +194,17 @@ pub enum GoCommand {
     Perft(Option<u64>),
 }
 
+impl GoCommand {
+    /// Every parameter of a `go` line, where `UciGui::Go` keeps only the first
+    pub fn parse_all(s: &str) -> Vec<Self> {
+        let input = s.split_whitespace().collect::<Vec<_>>();
+        let input = find_literal_uci("go", &input).unwrap_or(&[]);
+        parse_many_uci(input)
+            .map(|(res, _)| res)
+            .unwrap_or_default()
+    }
+}
+
 impl Uci for GoCommand {
     fn print(&self, output: &mut Vec<String>) {
         match self {
@@ -449,3 +449,25 @@ fn fun_joke() {
     );
     assert_eq!(parse, UciGui::Go(GoCommand::Depth(16)));
 }
+
+#[test]
+fn go_parse_all() {
+    assert_eq!(
+        GoCommand::parse_all("go ponder wtime 1000 btime 2000 winc 10 binc 20"),
+        vec![
+            GoCommand::Ponder(),
+            GoCommand::Time(TimeControl {
+                wtime: 1000,
+                btime: 2000,
+                winc: 10,
+                binc: 20,
+                moves_to_go: 0
+            })
+        ]
+    );
+    assert_eq!(
+        GoCommand::parse_all("go depth 6 nodes 5000"),
+        vec![GoCommand::Depth(6), GoCommand::Nodes(5000)]
+    );
+    assert_eq!(GoCommand::parse_all("stop"), vec![]);
+}
@@ -0,0 +1,231 @@
+use std::time::{Duration, Instant};
+
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    bits::board::BitBoard,
+    model::moves::ChessMove,
+    search::{MATE, MAX_PLY, Score, SearchLine, SearchResult},
+};
+
+/// What a problem asks of the side to move within its moves
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Stipulation {
+    /// Mate the opponent against every defence
+    Direct,
+    /// Force the opponent to give mate, against every defence
+    Selfmate,
+    /// Get mated by the opponent, with both sides cooperating
+    Helpmate,
+}
+
+/// A move of a solution with the replies it must be ready for,
+/// or every cooperating reply in a helpmate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MateTree {
+    pub mv: ChessMove,
+    pub replies: Vec<MateTree>,
+}
+
+impl MateTree {
+    /// The first line through the tree
+    pub fn line(&self) -> Vec<ChessMove> {
+        let mut res = vec![self.mv];
+        let mut node = self;
+        while let Some(next) = node.replies.first() {
+            res.push(next.mv);
+            node = next;
+        }
+        res
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MateResult {
+    /// Moves by the side to move in the shortest solution, if any was found
+    pub moves: Option<usize>,
+    /// Every first move solving in that many moves
+    pub solutions: Vec<MateTree>,
+    pub nodes: u64,
+    pub time: Duration,
+}
+
+impl MateResult {
+    /// The result as a search reporting a direct mate
+    pub fn search_result(&self) -> SearchResult {
+        let plies = self.moves.map(|n| n.saturating_mul(2).saturating_sub(1));
+        // Mates further away than the search can tell apart saturate
+        let score = plies.map_or(0, |p| MATE - p.min(MAX_PLY) as Score);
+        let lines = self
+            .solutions
+            .iter()
+            .map(|tree| SearchLine {
+                score,
+                pv: tree.line(),
+            })
+            .collect::<Vec<_>>();
+
+        SearchResult {
+            best: self.solutions.first().map(|tree| tree.mv),
+            score,
+            pv: lines.first().map(|l| l.pv.clone()).unwrap_or_default(),
+            lines,
+            depth: plies.map_or(0, |p| u8::try_from(p).unwrap_or(u8::MAX)),
+            nodes: self.nodes,
+            time: self.time,
+        }
+    }
+}
+
+/// Exhaustive search for problems of the form "mate in n"
+pub struct MateSearch {
+    pub board: BitBoard,
+    pub stipulation: Stipulation,
+    pub moves: usize,
+    pub stop: CancellationToken,
+    /// Gives up once this much time has passed
+    pub limit: Option<Duration>,
+    start: Instant,
+    nodes: u64,
+    stopped: bool,
+}
+
+impl MateSearch {
+    pub fn new(board: BitBoard, stipulation: Stipulation, moves: usize) -> Self {
+        Self {
+            board,
+            stipulation,
+            moves,
+            stop: CancellationToken::new(),
+            limit: None,
+            start: Instant::now(),
+            nodes: 0,
+            stopped: false,
+        }
+    }
+
+    /// Gives up as soon as possible once `stop` is cancelled
+    pub fn with_stop(mut self, stop: CancellationToken) -> Self {
+        self.stop = stop;
+        self
+    }
+
+    /// Gives up once `limit` has passed, if set
+    pub fn with_limit(mut self, limit: Option<Duration>) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Tries one move, then two and so on, stopping at the shortest solution
+    pub fn run(&mut self) -> MateResult {
+        self.start = Instant::now();
+        let start = self.start;
+        let mut res = MateResult {
+            moves: None,
+            solutions: vec![],
+            nodes: 0,
+            time: Duration::ZERO,
+        };
+
+        for n in 1..=self.moves {
+            let solutions = self.attack(n, true);
+            if self.stopped {
+                break;
+            }
+            if !solutions.is_empty() {
+                res.moves = Some(n);
+                res.solutions = solutions;
+                break;
+            }
+        }
+
+        res.nodes = self.nodes;
+        res.time = start.elapsed();
+        res
+    }
+
+    /// Moves of the side to move that meet the stipulation in `n` moves
+    fn attack(&mut self, n: usize, all: bool) -> Vec<MateTree> {
+        let mut res = vec![];
+
+        for mv in self.legal_moves() {
+            self.visit();
+            self.board.apply(mv);
+            let replies = self.defend(n);
+            self.board.unapply(mv);
+
+            if self.stopped {
+                return vec![];
+            }
+
+            if let Some(replies) = replies {
+                res.push(MateTree { mv, replies });
+                if !all {
+                    break;
+                }
+            }
+        }
+
+        res
+    }
+
+    /// The replies to an attacking move with their continuations,
+    /// or `None` if the opponent can escape
+    fn defend(&mut self, n: usize) -> Option<Vec<MateTree>> {
+        let moves = self.legal_moves();
+        if moves.is_empty() {
+            let mated = self.board.in_check();
+            return (self.stipulation == Stipulation::Direct && mated).then(Vec::new);
+        }
+        if self.stipulation == Stipulation::Direct && n == 1 {
+            return None;
+        }
+
+        let mut res = vec![];
+        for mv in moves {
+            self.visit();
+            self.board.apply(mv);
+            let direct = self.stipulation == Stipulation::Direct;
+            let continuation = if !direct && self.is_checkmate() {
+                Some(vec![])
+            } else if !direct && n == 1 {
+                None
+            } else {
+                let all = self.stipulation == Stipulation::Helpmate;
+                Some(self.attack(n - 1, all)).filter(|c| !c.is_empty())
+            };
+            self.board.unapply(mv);
+
+            if self.stopped {
+                return None;
+            }
+
+            match continuation {
+                Some(replies) => res.push(MateTree { mv, replies }),
+                None if self.stipulation == Stipulation::Helpmate => {}
+                None => return None,
+            }
+        }
+
+        (!res.is_empty()).then_some(res)
+    }
+
+    fn legal_moves(&self) -> Vec<ChessMove> {
+        let mut res = vec![];
+        self.board.moves(&mut res);
+        res
+    }
+
+    fn is_checkmate(&self) -> bool {
+        self.board.in_check() && self.legal_moves().is_empty()
+    }
+
+    fn visit(&mut self) {
+        self.nodes += 1;
+        if self.nodes.is_multiple_of(1024)
+            && (self.stop.is_cancelled() || self.limit.is_some_and(|t| self.start.elapsed() >= t))
+        {
+            self.stopped = true;
+        }
+    }
+}
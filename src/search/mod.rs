@@ -0,0 +1,546 @@
+pub mod mate;
+pub mod ordering;
+pub mod quiescence;
+pub mod staged;
+pub mod tests;
+pub mod time;
+
+use std::{
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    bits::board::BitBoard,
+    eval::{
+        EvalParams,
+        nnue::{Network, Nnue},
+    },
+    model::moves::ChessMove,
+    notation::LongAlg,
+    search::{ordering::MoveOrdering, staged::StagedMoves},
+    tablebase::{Tablebase, Wdl},
+    zobrist::{
+        ZobHash,
+        table::{Bound, TableEntry, ZobTable},
+    },
+};
+
+pub type Score = i32;
+
+pub const INFINITY: Score = 32_000;
+pub const MATE: Score = 31_000;
+pub const MAX_PLY: usize = 128;
+pub const DEFAULT_HASH_MB: usize = 16;
+
+pub const fn is_mate(score: Score) -> bool {
+    score.abs() >= MATE - MAX_PLY as Score
+}
+
+/// Plies until mate, positive if the side to move delivers it
+pub const fn mate_distance(score: Score) -> Option<i32> {
+    if !is_mate(score) {
+        None
+    } else if score > 0 {
+        Some(MATE - score)
+    } else {
+        Some(-MATE - score)
+    }
+}
+
+/// Mate scores are stored relative to the node rather than the root
+pub const fn score_to_table(score: Score, ply: usize) -> Score {
+    if !is_mate(score) {
+        score
+    } else if score > 0 {
+        score + ply as Score
+    } else {
+        score - ply as Score
+    }
+}
+
+pub const fn score_from_table(score: Score, ply: usize) -> Score {
+    if !is_mate(score) {
+        score
+    } else if score > 0 {
+        score - ply as Score
+    } else {
+        score + ply as Score
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SearchLimits {
+    pub depth: u8 = MAX_PLY as u8 - 1,
+    pub nodes: u64 = u64::MAX,
+    pub soft: Option<Duration> = None,
+    pub hard: Option<Duration> = None,
+}
+
+impl SearchLimits {
+    pub fn depth(depth: u8) -> Self {
+        Self { depth, .. }
+    }
+
+    pub fn nodes(nodes: u64) -> Self {
+        Self { nodes, .. }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchResult {
+    pub best: Option<ChessMove>,
+    pub score: Score,
+    pub pv: Vec<ChessMove>,
+    pub lines: Vec<SearchLine>,
+    pub depth: u8,
+    pub nodes: u64,
+    pub time: Duration,
+}
+
+/// One of the ranked root moves of a multi-PV search
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchLine {
+    pub score: Score,
+    pub pv: Vec<ChessMove>,
+}
+
+impl SearchResult {
+    pub fn ponder(&self) -> Option<ChessMove> {
+        self.pv.get(1).copied()
+    }
+}
+
+impl BitBoard {
+    pub fn search(&self, limits: SearchLimits) -> SearchResult {
+        let table = Arc::new(ZobTable::megabytes(DEFAULT_HASH_MB));
+        Search::new(self.clone(), limits, table).run(|_| {})
+    }
+}
+
+pub struct Search {
+    pub board: BitBoard,
+    pub limits: SearchLimits,
+    pub history: Vec<ZobHash>,
+    pub table: Arc<ZobTable>,
+    pub ordering: MoveOrdering,
+    pub stop: CancellationToken,
+    pub multipv: usize,
+    pub threads: usize,
+    pub tablebase: Option<Arc<dyn Tablebase + Send + Sync>>,
+    pub params: Arc<EvalParams>,
+    pub network: Option<Arc<Network>>,
+    nnue: Option<Nnue>,
+    helper: usize,
+    excluded: Vec<LongAlg>,
+    refuted: Vec<LongAlg>,
+    start: Instant,
+    nodes: u64,
+    stopped: bool,
+    pv: Vec<Vec<ChessMove>>,
+    prev_pv: Vec<ChessMove>,
+    buffers: Vec<Vec<ChessMove>>,
+}
+
+impl Search {
+    /// The transposition table may be shared with other searches
+    pub fn new(board: BitBoard, limits: SearchLimits, table: Arc<ZobTable>) -> Self {
+        Self {
+            board,
+            limits,
+            history: vec![],
+            table,
+            ordering: MoveOrdering::default(),
+            stop: CancellationToken::new(),
+            multipv: 1,
+            threads: 1,
+            tablebase: None,
+            params: Arc::new(EvalParams::default()),
+            network: None,
+            nnue: None,
+            helper: 0,
+            excluded: vec![],
+            refuted: vec![],
+            start: Instant::now(),
+            nodes: 0,
+            stopped: false,
+            pv: vec![vec![]; MAX_PLY + 1],
+            prev_pv: vec![],
+            buffers: vec![vec![]; MAX_PLY + 1],
+        }
+    }
+
+    /// Hashes of the positions preceding the root, for repetition detection
+    pub fn with_history(mut self, history: Vec<ZobHash>) -> Self {
+        self.history = history;
+        self
+    }
+
+    /// Searches the best `multipv` root moves, each as its own line
+    pub fn with_multipv(mut self, multipv: usize) -> Self {
+        self.multipv = multipv;
+        self
+    }
+
+    /// Lazy SMP: helper threads search the same root and
+    /// only share their findings through the table
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Root moves the tablebase shows to be worse than the best are never played
+    pub fn with_tablebase(mut self, tablebase: Arc<dyn Tablebase + Send + Sync>) -> Self {
+        self.tablebase = Some(tablebase);
+        self
+    }
+
+    /// Evaluates leaves with `params` instead of the built-in weights
+    pub fn with_params(mut self, params: Arc<EvalParams>) -> Self {
+        self.params = params;
+        self
+    }
+
+    /// Evaluates leaves with `network`, whose accumulator
+    /// is updated incrementally as moves are made and unmade
+    pub fn with_network(mut self, network: Arc<Network>) -> Self {
+        self.network = Some(network);
+        self
+    }
+
+    /// Aborts the search as soon as possible once `stop` is cancelled
+    pub fn with_stop(mut self, stop: CancellationToken) -> Self {
+        self.stop = stop;
+        self
+    }
+
+    /// Iterative deepening, calling `report` after every completed depth
+    pub fn run(&mut self, report: impl FnMut(&SearchResult)) -> SearchResult {
+        if self.threads <= 1 {
+            return self.iterate(report);
+        }
+
+        self.table.new_search();
+        let helpers = self.stop.child_token();
+
+        thread::scope(|scope| {
+            let handles = (1..self.threads)
+                .map(|id| {
+                    let mut helper =
+                        Search::new(self.board.clone(), self.limits, self.table.clone())
+                            .with_history(self.history.clone())
+                            .with_stop(helpers.clone());
+                    helper.tablebase = self.tablebase.clone();
+                    helper.params = self.params.clone();
+                    helper.network = self.network.clone();
+                    helper.helper = id;
+                    scope.spawn(move || {
+                        helper.iterate(|_| {});
+                        helper.nodes
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            let mut res = self.iterate(report);
+            helpers.cancel();
+
+            res.nodes += handles
+                .into_iter()
+                .filter_map(|h| h.join().ok())
+                .sum::<u64>();
+            res
+        })
+    }
+
+    fn iterate(&mut self, mut report: impl FnMut(&SearchResult)) -> SearchResult {
+        self.start = Instant::now();
+        let mut res = SearchResult {
+            best: None,
+            score: 0,
+            pv: vec![],
+            lines: vec![],
+            depth: 0,
+            nodes: 0,
+            time: Duration::ZERO,
+        };
+
+        if self.threads <= 1 && self.helper == 0 {
+            self.table.new_search();
+        }
+        self.ordering.age();
+        self.nnue = self.network.clone().map(|n| Nnue::new(n, &self.board));
+
+        let mut root = vec![];
+        self.board.moves(&mut root);
+        self.refuted = self.tablebase_refutations(&root);
+        let multipv = self
+            .multipv
+            .clamp(1, (root.len() - self.refuted.len()).max(1));
+
+        let max_depth = self.limits.depth.max(1);
+        for depth in 1..=max_depth {
+            // Every other helper runs a ply ahead to spread the work
+            let depth = (depth + (self.helper % 2) as u8).min(max_depth);
+            let mut score = 0;
+            let mut lines = vec![];
+            self.excluded.clear();
+
+            for i in 0..multipv {
+                self.prev_pv = res.lines.get(i).map(|l| l.pv.clone()).unwrap_or_default();
+                let line_score = self.negamax(depth, 0, -INFINITY, INFINITY);
+
+                if self.stopped && i > 0 {
+                    break;
+                }
+
+                if i == 0 {
+                    score = line_score;
+                }
+
+                if let Some(mv) = self.pv[0].first() {
+                    self.excluded.push(mv.simplify());
+                    lines.push(SearchLine {
+                        score: line_score,
+                        pv: self.pv[0].clone(),
+                    });
+                }
+
+                if self.stopped {
+                    break;
+                }
+            }
+
+            if self.stopped && res.best.is_some() {
+                break;
+            }
+
+            res = SearchResult {
+                best: lines.first().and_then(|l| l.pv.first().copied()),
+                score,
+                pv: lines.first().map(|l| l.pv.clone()).unwrap_or_default(),
+                lines,
+                depth,
+                nodes: self.nodes,
+                time: self.start.elapsed(),
+            };
+
+            report(&res);
+
+            if self.stopped {
+                break;
+            }
+
+            if let Some(n) = mate_distance(score)
+                && n.unsigned_abs() <= depth as u32
+            {
+                break;
+            }
+
+            if let Some(soft) = self.limits.soft
+                && self.start.elapsed() >= soft
+            {
+                break;
+            }
+        }
+
+        res.nodes = self.nodes;
+        res.time = self.start.elapsed();
+        res
+    }
+
+    fn negamax(&mut self, depth: u8, ply: usize, mut alpha: Score, beta: Score) -> Score {
+        self.pv[ply].clear();
+
+        if self.stopped {
+            return 0;
+        }
+
+        self.visit();
+
+        if ply > 0 && self.is_draw() {
+            return 0;
+        }
+
+        if depth == 0 || ply >= MAX_PLY {
+            return self.quiescence(ply, alpha, beta);
+        }
+
+        let key = self.board.metadata.hash;
+        let entry = self.table.get(key);
+
+        if ply > 0
+            && let Some(entry) = entry
+            && entry.depth >= depth
+        {
+            let score = score_from_table(entry.score, ply);
+            let cutoff = match entry.bound {
+                Bound::Exact => score <= alpha || score >= beta,
+                Bound::Lower => score >= beta,
+                Bound::Upper => score <= alpha,
+            };
+            if cutoff {
+                return score;
+            }
+        }
+
+        let table_move = entry
+            .and_then(|e| e.best)
+            .or(self.prev_pv.get(ply).map(|mv| mv.simplify()));
+        let mut staged = StagedMoves::new(ply, table_move);
+        let mut tried = std::mem::take(&mut self.buffers[ply]);
+        tried.clear();
+
+        let original_alpha = alpha;
+        let mut best = -INFINITY;
+        let mut best_move = None;
+        let mut legal = false;
+
+        while let Some(mv) = staged.next_move(&self.board, &self.ordering) {
+            legal = true;
+            if ply == 0
+                && (self.excluded.contains(&mv.simplify()) || self.refuted.contains(&mv.simplify()))
+            {
+                continue;
+            }
+
+            self.history.push(self.board.metadata.hash);
+            self.make(mv);
+            let score = -self.negamax(depth - 1, ply + 1, -beta, -alpha);
+            self.unmake(mv);
+            self.history.pop();
+
+            if self.stopped {
+                break;
+            }
+
+            if score > best {
+                best = score;
+                best_move = Some(mv.simplify());
+            }
+
+            if score > alpha {
+                alpha = score;
+                let (head, tail) = self.pv.split_at_mut(ply + 1);
+                head[ply].clear();
+                head[ply].push(mv);
+                head[ply].extend_from_slice(&tail[0]);
+            }
+
+            if alpha >= beta {
+                self.ordering.cutoff(mv, ply, depth, &tried);
+                break;
+            }
+            tried.push(mv);
+        }
+
+        self.buffers[ply] = tried;
+
+        if !legal {
+            return if self.board.in_check() {
+                -MATE + ply as Score
+            } else {
+                0
+            };
+        }
+
+        if !self.stopped && (ply > 0 || self.excluded.is_empty() && self.refuted.is_empty()) {
+            let bound = if best >= beta {
+                Bound::Lower
+            } else if best > original_alpha {
+                Bound::Exact
+            } else {
+                Bound::Upper
+            };
+
+            self.table.insert(
+                key,
+                TableEntry {
+                    best: if bound == Bound::Upper {
+                        None
+                    } else {
+                        best_move
+                    },
+                    score: score_to_table(best, ply),
+                    depth,
+                    bound,
+                },
+            );
+        }
+
+        best
+    }
+
+    /// Root moves with a worse tablebase outcome than the best known one;
+    /// moves the tablebase cannot probe are left for the search to judge
+    fn tablebase_refutations(&mut self, root: &[ChessMove]) -> Vec<LongAlg> {
+        let Some(tb) = self.tablebase.clone() else {
+            return vec![];
+        };
+
+        if !tb.covers(&self.board) {
+            return vec![];
+        }
+
+        let mut outcomes = vec![];
+        for &mv in root {
+            self.board.apply(mv);
+            let wdl = tb.probe_wdl(&self.board);
+            self.board.unapply(mv);
+
+            if let Ok(wdl) = wdl {
+                outcomes.push((mv.simplify(), wdl.opposite()));
+            }
+        }
+
+        let best = outcomes.iter().map(|(_, wdl)| *wdl).max();
+        outcomes
+            .into_iter()
+            .filter(|(_, wdl)| Some(*wdl) < best)
+            .map(|(mv, _)| mv)
+            .collect()
+    }
+
+    /// Plays `mv`, keeping the network accumulator in step with the board
+    pub(super) fn make(&mut self, mv: ChessMove) {
+        self.board.apply(mv);
+        if let Some(nnue) = &mut self.nnue {
+            nnue.delta(mv, self.board.metadata.castling_details, true);
+        }
+    }
+
+    pub(super) fn unmake(&mut self, mv: ChessMove) {
+        self.board.unapply(mv);
+        if let Some(nnue) = &mut self.nnue {
+            nnue.delta(mv, self.board.metadata.castling_details, false);
+        }
+    }
+
+    fn visit(&mut self) {
+        self.nodes += 1;
+
+        if self.nodes >= self.limits.nodes {
+            self.stopped = true;
+        }
+
+        if self.nodes.is_multiple_of(1024)
+            && (self.stop.is_cancelled()
+                || self.limits.hard.is_some_and(|t| self.start.elapsed() >= t))
+        {
+            self.stopped = true;
+        }
+    }
+
+    fn is_draw(&self) -> bool {
+        let hmc = self.board.metadata.halfmove_clock as usize;
+        hmc >= 100
+            || self
+                .history
+                .iter()
+                .rev()
+                .take(hmc)
+                .any(|h| *h == self.board.metadata.hash)
+    }
+}
@@ -0,0 +1,118 @@
+use crate::{
+    bits::exchange::piece_value,
+    model::{
+        Color,
+        moves::{ChessMove, SpecialMove},
+    },
+    notation::LongAlg,
+    search::MAX_PLY,
+};
+
+const TABLE_MOVE: i32 = 1 << 24;
+const TACTICAL: i32 = 1 << 20;
+const KILLERS: [i32; 2] = [TACTICAL - 1, TACTICAL - 2];
+const HISTORY_MAX: i32 = 1 << 16;
+
+/// Killer moves and butterfly history gathered while searching
+#[derive(Debug, Clone)]
+pub struct MoveOrdering {
+    pub killers: Vec<[Option<LongAlg>; 2]>,
+    pub history: [[i32; 64]; 12],
+}
+
+impl Default for MoveOrdering {
+    fn default() -> Self {
+        Self {
+            killers: vec![[None; 2]; MAX_PLY + 1],
+            history: [[0; 64]; 12],
+        }
+    }
+}
+
+impl MoveOrdering {
+    /// Table move first, then captures and promotions by most valuable
+    /// victim, then killers, then quiet moves by history
+    pub fn order(&self, moves: &mut [ChessMove], ply: usize, table_move: Option<LongAlg>) {
+        moves.sort_by_cached_key(|mv| -self.score(*mv, ply, table_move));
+    }
+
+    pub fn score(&self, mv: ChessMove, ply: usize, table_move: Option<LongAlg>) -> i32 {
+        let simple = mv.simplify();
+
+        if Some(simple) == table_move {
+            TABLE_MOVE
+        } else if is_tactical(mv) {
+            TACTICAL - mvv_lva(mv) as i32
+        } else if let Some(k) = self.killers[ply].iter().position(|k| *k == Some(simple)) {
+            KILLERS[k]
+        } else {
+            self.history(mv)
+        }
+    }
+
+    /// Rewards the quiet move that caused a beta cutoff and
+    /// penalizes the quiet moves tried before it
+    pub fn cutoff(&mut self, mv: ChessMove, ply: usize, depth: u8, tried: &[ChessMove]) {
+        if is_tactical(mv) {
+            return;
+        }
+
+        let killers = &mut self.killers[ply];
+        if killers[0] != Some(mv.simplify()) {
+            killers[1] = killers[0];
+            killers[0] = Some(mv.simplify());
+        }
+
+        let bonus = (depth as i32 * depth as i32).min(HISTORY_MAX);
+        self.adjust(mv, bonus);
+
+        for &other in tried {
+            if other != mv && !is_tactical(other) {
+                self.adjust(other, -bonus);
+            }
+        }
+    }
+
+    /// Decays history between searches and forgets killers
+    pub fn age(&mut self) {
+        for h in self.history.as_flattened_mut() {
+            *h /= 2;
+        }
+        self.killers.fill([None; 2]);
+    }
+
+    fn adjust(&mut self, mv: ChessMove, bonus: i32) {
+        let h = self.history_mut(mv);
+        *h += bonus - *h * bonus.abs() / HISTORY_MAX;
+    }
+
+    fn history(&self, mv: ChessMove) -> i32 {
+        self.history[index(mv)][mv.pmv.to.ix() as usize]
+    }
+
+    fn history_mut(&mut self, mv: ChessMove) -> &mut i32 {
+        &mut self.history[index(mv)][mv.pmv.to.ix() as usize]
+    }
+}
+
+fn index(mv: ChessMove) -> usize {
+    let offset = match mv.cpc.color() {
+        Color::White => 0,
+        Color::Black => 6,
+    };
+    offset + mv.cpc.piece() as usize - 1
+}
+
+pub fn is_tactical(mv: ChessMove) -> bool {
+    mv.cap.is_some() || matches!(mv.spc, Some(SpecialMove::Promotion(_)))
+}
+
+/// Most valuable victim, least valuable attacker
+pub fn mvv_lva(mv: ChessMove) -> i16 {
+    let victim = mv.cpc.capture().map(piece_value).unwrap_or(0);
+    let promotion = match mv.spc {
+        Some(SpecialMove::Promotion(p)) => piece_value(p),
+        _ => 0,
+    };
+    mv.cpc.piece() as i16 - 10 * (victim + promotion)
+}
@@ -0,0 +1,74 @@
+use crate::search::{INFINITY, MATE, MAX_PLY, Score, Search, ordering::mvv_lva};
+
+impl Search {
+    /// Side-to-move relative score of the current position, from the
+    /// network if there is one or else from `params`
+    pub(super) fn evaluate(&self) -> Score {
+        if let Some(nnue) = &self.nnue {
+            return nnue.evaluate(self.board.metadata.to_move);
+        }
+        self.params.evaluate(&self.board)
+    }
+
+    /// Resolves captures and promotions until the position is quiet,
+    /// searching every evasion when in check
+    pub(super) fn quiescence(&mut self, ply: usize, mut alpha: Score, beta: Score) -> Score {
+        self.pv[ply].clear();
+
+        if self.stopped {
+            return 0;
+        }
+
+        self.visit();
+
+        if ply >= MAX_PLY {
+            return self.evaluate();
+        }
+
+        let in_check = self.board.in_check();
+        let mut best = -INFINITY;
+
+        if !in_check {
+            best = self.evaluate();
+            if best >= beta {
+                return best;
+            }
+            alpha = alpha.max(best);
+        }
+
+        let mut moves = std::mem::take(&mut self.buffers[ply]);
+        moves.clear();
+        self.board.tactical_moves(true, &mut moves);
+
+        if in_check && moves.is_empty() {
+            self.buffers[ply] = moves;
+            return -MATE + ply as Score;
+        }
+
+        moves.sort_by_key(|mv| mvv_lva(*mv));
+
+        for &mv in &moves {
+            if !in_check && mv.cap.is_some() && self.board.see(mv) < 0 {
+                continue;
+            }
+
+            self.make(mv);
+            let score = -self.quiescence(ply + 1, -beta, -alpha);
+            self.unmake(mv);
+
+            if self.stopped {
+                break;
+            }
+
+            best = best.max(score);
+            alpha = alpha.max(score);
+
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        self.buffers[ply] = moves;
+        best
+    }
+}
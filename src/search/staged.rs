@@ -0,0 +1,178 @@
+use crate::{
+    bits::board::BitBoard,
+    model::moves::{ChessMove, PseudoMove},
+    notation::LongAlg,
+    search::ordering::{MoveOrdering, mvv_lva},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Stage {
+    TableMove,
+    GoodCaptures,
+    Killers,
+    Quiets,
+    BadCaptures,
+    Done,
+}
+
+/// Legal moves generated a stage at a time: the table move, captures and
+/// promotions that do not lose material, killers, quiet moves by history
+/// and finally losing captures. Quiet table moves and killers are checked
+/// on their own, so quiet moves are only generated once they run out.
+///
+/// Every call to `next_move` must be given the same position.
+#[derive(Debug, Clone)]
+pub struct StagedMoves {
+    ply: usize,
+    table_move: Option<LongAlg>,
+    pub stage: Stage,
+    killer: usize,
+    tactical: Option<Vec<ChessMove>>,
+    quiet: Option<Vec<ChessMove>>,
+    /// Quiet moves already returned, which the quiet stage skips
+    played: Vec<ChessMove>,
+    bad: Vec<ChessMove>,
+}
+
+impl StagedMoves {
+    pub fn new(ply: usize, table_move: Option<LongAlg>) -> Self {
+        Self {
+            ply,
+            table_move,
+            stage: Stage::TableMove,
+            killer: 0,
+            tactical: None,
+            quiet: None,
+            played: vec![],
+            bad: vec![],
+        }
+    }
+
+    /// The next move in `board`, using the killers and history of `ordering`
+    pub fn next_move(&mut self, board: &BitBoard, ordering: &MoveOrdering) -> Option<ChessMove> {
+        loop {
+            match self.stage {
+                Stage::TableMove => {
+                    self.stage = Stage::GoodCaptures;
+                    if let Some(mv) = self
+                        .table_move
+                        .and_then(|tm| self.take_table_move(board, tm))
+                    {
+                        return Some(mv);
+                    }
+                }
+                Stage::GoodCaptures => {
+                    self.tactical(board);
+                    while let Some(mv) = self.tactical.as_mut().and_then(Vec::pop) {
+                        if board.see(mv) >= 0 {
+                            return Some(mv);
+                        }
+                        self.bad.push(mv);
+                    }
+                    self.stage = Stage::Killers;
+                }
+                Stage::Killers => {
+                    let killers = ordering.killers[self.ply];
+                    let Some(&killer) = killers.get(self.killer) else {
+                        self.stage = Stage::Quiets;
+                        continue;
+                    };
+                    self.killer += 1;
+
+                    if let Some((pmv, None)) = killer
+                        && let Some(mv) = self.take_quiet(board, pmv)
+                    {
+                        return Some(mv);
+                    }
+                }
+                Stage::Quiets => {
+                    while let Some(mv) = self.quiet(board, ordering).pop() {
+                        if !self.played.contains(&mv) {
+                            return Some(mv);
+                        }
+                    }
+                    self.stage = Stage::BadCaptures;
+                    self.bad.reverse();
+                }
+                Stage::BadCaptures => {
+                    if let Some(mv) = self.bad.pop() {
+                        return Some(mv);
+                    }
+                    self.stage = Stage::Done;
+                }
+                Stage::Done => return None,
+            }
+        }
+    }
+
+    /// Removes the table move from whichever stage would generate it
+    fn take_table_move(&mut self, board: &BitBoard, tm: LongAlg) -> Option<ChessMove> {
+        let (pmv, promotion) = tm;
+        let (friendly, enemy) = board.active_passive(board.metadata.to_move);
+        let en_passant =
+            friendly.pawns & pmv.from.bit() != 0 && board.metadata.en_passant == Some(pmv.to);
+        let capture = enemy.total & pmv.to.bit() != 0 || en_passant;
+
+        if !capture && promotion.is_none() {
+            if let Some(mv) = self.take_quiet(board, pmv) {
+                return Some(mv);
+            }
+
+            // Castling is not checked on its own
+            let mut moves = vec![];
+            board.quiet_moves(&mut moves);
+            let mv = moves.into_iter().find(|mv| mv.simplify() == tm)?;
+            self.played.push(mv);
+            return Some(mv);
+        }
+
+        let moves = self.tactical(board);
+        let ix = moves.iter().position(|mv| mv.simplify() == tm)?;
+        Some(moves.remove(ix))
+    }
+
+    /// `pmv` if it is a legal quiet move not yet played
+    fn take_quiet(&mut self, board: &BitBoard, pmv: PseudoMove) -> Option<ChessMove> {
+        let played = self.played.len();
+        board.quiet_move(pmv, &mut self.played);
+        let mv = *self.played.get(played)?;
+        if self.played[..played].contains(&mv) {
+            self.played.pop();
+            return None;
+        }
+        Some(mv)
+    }
+
+    /// Captures and promotions, best last
+    fn tactical(&mut self, board: &BitBoard) -> &mut Vec<ChessMove> {
+        self.tactical.get_or_insert_with(|| {
+            let mut res = vec![];
+            board.tactical_moves(false, &mut res);
+            res.sort_by_key(|mv| -mvv_lva(*mv));
+            res
+        })
+    }
+
+    /// Quiet moves by history, best last
+    fn quiet(&mut self, board: &BitBoard, ordering: &MoveOrdering) -> &mut Vec<ChessMove> {
+        self.quiet.get_or_insert_with(|| {
+            let mut res = vec![];
+            board.quiet_moves(&mut res);
+            res.sort_by_cached_key(|mv| ordering.score(*mv, self.ply, None));
+            res
+        })
+    }
+}
+
+impl BitBoard {
+    /// Legal moves in the order a search wants to try them, see `StagedMoves`
+    pub fn staged_moves<'a>(
+        &'a self,
+        ordering: &'a MoveOrdering,
+        ply: usize,
+        table_move: Option<LongAlg>,
+    ) -> impl Iterator<Item = ChessMove> + 'a {
+        let mut staged = StagedMoves::new(ply, table_move);
+        std::iter::from_fn(move || staged.next_move(self, ordering))
+    }
+}
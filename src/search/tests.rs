@@ -0,0 +1,466 @@
+use std::{sync::Arc, time::Duration};
+
+use rand::seq::IndexedRandom;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    bits::board::BitBoard,
+    eval::{EvalParams, nnue::Network},
+    fuzzing::pi_rng,
+    model::{Color, Square, moves::ChessMove},
+    notation::{
+        fen::{parse_fen, render_fen},
+        uci::gui::{GoCommand, TimeControl},
+    },
+    search::{
+        MATE, MAX_PLY, Score, Search, SearchLimits,
+        mate::{MateResult, MateSearch, MateTree, Stipulation},
+        mate_distance,
+        ordering::{MoveOrdering, is_tactical},
+    },
+    zobrist::table::ZobTable,
+};
+
+#[test]
+fn search_startpos_legal() {
+    let board = BitBoard::startpos();
+    let res = board.search(SearchLimits::depth(3));
+
+    let mut moves = vec![];
+    board.moves(&mut moves);
+
+    assert_eq!(res.depth, 3);
+    assert!(moves.contains(&res.best.unwrap()));
+    assert_eq!(res.pv.len(), 3);
+
+    let mut board = board;
+    for mv in &res.pv {
+        let mut legal = vec![];
+        board.moves(&mut legal);
+        assert!(legal.contains(mv), "{} not legal", mv.longalg());
+        board.apply(*mv);
+    }
+}
+
+#[test]
+fn search_mate_in_one() {
+    let board = parse_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+    let res = board.search(SearchLimits::depth(4));
+
+    assert_eq!(res.best.unwrap().pmv, Square::a1.to(Square::a8));
+    assert_eq!(res.score, MATE - 1);
+    assert_eq!(mate_distance(res.score), Some(1));
+}
+
+#[test]
+fn search_mate_in_two() {
+    let board = parse_fen("k7/8/2K5/8/8/8/7R/8 w - - 0 1").unwrap();
+    let res = board.search(SearchLimits::depth(5));
+
+    assert_eq!(mate_distance(res.score), Some(3));
+}
+
+#[test]
+fn search_hanging_queen() {
+    let board = parse_fen("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1").unwrap();
+    let res = board.search(SearchLimits::depth(2));
+
+    assert_eq!(res.best.unwrap().pmv, Square::d2.to(Square::d5));
+}
+
+#[test]
+fn search_with_params() {
+    let board = parse_fen("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1").unwrap();
+    let rxq = Square::d2.to(Square::d5);
+    assert_eq!(board.search(SearchLimits::depth(2)).best.unwrap().pmv, rxq);
+
+    // With queens as a liability the rook leaves it be
+    let mut params = EvalParams::default();
+    params.middlegame.material.queen = -2000;
+    params.endgame.material.queen = -2000;
+    let res = Search::new(
+        board,
+        SearchLimits::depth(2),
+        Arc::new(ZobTable::megabytes(1)),
+    )
+    .with_params(Arc::new(params))
+    .run(|_| {});
+    assert_ne!(res.best.unwrap().pmv, rxq);
+}
+
+#[test]
+fn search_with_network() {
+    let network = Network::from_bytes(include_bytes!("../../tests/tiny.nnue")).unwrap();
+    let board = parse_fen("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1").unwrap();
+    let res = Search::new(
+        board.clone(),
+        SearchLimits::depth(4),
+        Arc::new(ZobTable::megabytes(1)),
+    )
+    .with_network(Arc::new(network))
+    .with_threads(2)
+    .run(|_| {});
+
+    assert_eq!(res.best.unwrap().pmv, Square::d2.to(Square::d5));
+    assert!(res.score > 300);
+}
+
+#[test]
+fn search_stalemate() {
+    let board = parse_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+    let res = board.search(SearchLimits::depth(3));
+
+    assert_eq!(res.best, None);
+    assert_eq!(res.score, 0);
+}
+
+#[test]
+fn search_node_limit() {
+    let res = BitBoard::startpos().search(SearchLimits::nodes(1000));
+
+    assert!(res.best.is_some());
+    assert!(res.nodes <= 1000);
+}
+
+#[test]
+fn search_quiescence_defended_pawn() {
+    let board = parse_fen("4k3/8/2p5/3p4/8/8/8/3QK3 w - - 0 1").unwrap();
+    let res = board.search(SearchLimits::depth(1));
+
+    assert_ne!(res.best.unwrap().pmv, Square::d1.to(Square::d5));
+    assert!(res.score > 500);
+}
+
+#[test]
+fn move_ordering() {
+    let board = parse_fen("4k3/8/2q5/3p4/1N2P3/8/8/4K2R w - - 0 1").unwrap();
+    let mut moves = vec![];
+    board.moves(&mut moves);
+
+    let mut ordering = MoveOrdering::default();
+    let quiet = *moves
+        .iter()
+        .find(|m| m.pmv == Square::h1.to(Square::h5))
+        .unwrap();
+    let tried = moves
+        .iter()
+        .filter(|m| m.cap.is_none())
+        .copied()
+        .collect::<Vec<_>>();
+    ordering.cutoff(quiet, 3, 4, &tried);
+
+    let table = Square::b4.to(Square::d3);
+    ordering.order(&mut moves, 3, Some((table, None)));
+
+    let order = moves.iter().map(|m| m.pmv).collect::<Vec<_>>();
+    assert_eq!(
+        order[..5],
+        [
+            table,
+            Square::b4.to(Square::c6),
+            Square::e4.to(Square::d5),
+            Square::b4.to(Square::d5),
+            Square::h1.to(Square::h5),
+        ]
+    );
+
+    ordering.order(&mut moves, 2, None);
+    assert_eq!(moves[3].pmv, Square::h1.to(Square::h5));
+}
+
+#[test]
+fn time_management() {
+    let tc = TimeControl {
+        wtime: 60_000,
+        btime: 1_000,
+        winc: 1_000,
+        binc: 0,
+        moves_to_go: 0,
+    };
+
+    let white = SearchLimits::from_go(&GoCommand::Time(tc), Color::White);
+    let black = SearchLimits::from_go(&GoCommand::Time(tc), Color::Black);
+    assert!(white.soft.unwrap() > black.soft.unwrap());
+    assert!(white.soft <= white.hard);
+    assert!(black.hard.unwrap() < Duration::from_millis(1_000));
+
+    let movetime = SearchLimits::from_go(&GoCommand::Movetime(500), Color::White);
+    assert_eq!(movetime.soft, movetime.hard);
+    assert!(movetime.hard.unwrap() <= Duration::from_millis(500));
+
+    assert_eq!(
+        SearchLimits::from_go(&GoCommand::Depth(7), Color::White),
+        SearchLimits::depth(7)
+    );
+    assert_eq!(
+        SearchLimits::from_go(&GoCommand::Mate(2), Color::Black),
+        SearchLimits::depth(3)
+    );
+    assert_eq!(
+        SearchLimits::from_go(&GoCommand::Infinite(), Color::White),
+        SearchLimits::default()
+    );
+
+    let combined = SearchLimits::from_go_all(
+        &[
+            GoCommand::Ponder(),
+            GoCommand::Time(tc),
+            GoCommand::Depth(9),
+            GoCommand::Movetime(500),
+        ],
+        Color::White,
+    );
+    assert_eq!(combined.depth, 9);
+    assert_eq!(combined.hard, movetime.hard.min(white.hard));
+}
+
+#[test]
+fn search_deadlines() {
+    let limits = SearchLimits::from_go(&GoCommand::Movetime(100), Color::White);
+    let res = BitBoard::startpos().search(limits);
+    assert!(res.best.is_some());
+    assert!(res.time < Duration::from_millis(1_000));
+
+    let stop = CancellationToken::new();
+    stop.cancel();
+    let res = Search::new(
+        BitBoard::startpos(),
+        SearchLimits::default(),
+        Arc::new(ZobTable::megabytes(1)),
+    )
+    .with_stop(stop)
+    .run(|_| {});
+    assert!(res.best.is_some());
+    assert!(res.depth < 10);
+}
+
+#[test]
+fn search_multipv() {
+    let board = parse_fen("4k3/8/8/3q4/8/2N5/3R4/4K3 w - - 0 1").unwrap();
+    let res = Search::new(
+        board,
+        SearchLimits::depth(3),
+        Arc::new(ZobTable::megabytes(1)),
+    )
+    .with_multipv(3)
+    .run(|res| assert_eq!(res.lines.len(), 3));
+
+    assert_eq!(res.pv, res.lines[0].pv);
+    assert_eq!(res.score, res.lines[0].score);
+    assert!(res.lines.windows(2).all(|w| w[0].score >= w[1].score));
+
+    let firsts = res.lines.iter().map(|l| l.pv[0].pmv).collect::<Vec<_>>();
+    assert!(firsts[..2].contains(&Square::c3.to(Square::d5)));
+    assert!(firsts[..2].contains(&Square::d2.to(Square::d5)));
+    assert!(!firsts[..2].contains(&firsts[2]));
+
+    // More lines requested than there are legal moves
+    let board = parse_fen("7k/8/8/8/8/8/8/K6R b - - 0 1").unwrap();
+    let res = Search::new(
+        board,
+        SearchLimits::depth(2),
+        Arc::new(ZobTable::megabytes(1)),
+    )
+    .with_multipv(10)
+    .run(|_| {});
+    assert_eq!(res.lines.len(), 2);
+}
+
+#[test]
+fn search_threads() {
+    let board = parse_fen("k7/8/2K5/8/8/8/7R/8 w - - 0 1").unwrap();
+    let res = Search::new(
+        board,
+        SearchLimits::depth(6),
+        Arc::new(ZobTable::megabytes(1)),
+    )
+    .with_threads(4)
+    .run(|_| {});
+    assert_eq!(mate_distance(res.score), Some(3));
+
+    let board = parse_fen("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1").unwrap();
+    let single = board.search(SearchLimits::depth(4));
+    let threaded = Search::new(
+        board.clone(),
+        SearchLimits::depth(4),
+        Arc::new(ZobTable::megabytes(1)),
+    )
+    .with_threads(3)
+    .run(|_| {});
+    assert_eq!(threaded.best.unwrap().pmv, Square::d2.to(Square::d5));
+
+    // A single thread stays deterministic
+    let again = board.search(SearchLimits::depth(4));
+    assert_eq!(
+        (single.pv, single.score, single.nodes),
+        (again.pv, again.score, again.nodes)
+    );
+}
+
+/// Plays out every branch of `trees`, checking the opponent of the
+/// side to move at the root is mated at each leaf
+#[cfg(test)]
+fn assert_mates(board: &mut BitBoard, trees: &[MateTree], attacker: Color) {
+    for tree in trees {
+        board.apply(tree.mv);
+        if tree.replies.is_empty() {
+            let mut moves = vec![];
+            board.moves(&mut moves);
+            assert!(moves.is_empty() && board.in_check());
+            assert_eq!(board.metadata.to_move, attacker.opposite());
+        }
+        assert_mates(board, &tree.replies, attacker);
+        board.unapply(tree.mv);
+    }
+}
+
+#[test]
+fn mate_search_direct() {
+    let mut board = parse_fen("kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1").unwrap();
+
+    assert_eq!(
+        MateSearch::new(board.clone(), Stipulation::Direct, 1)
+            .run()
+            .moves,
+        None
+    );
+
+    let res = MateSearch::new(board.clone(), Stipulation::Direct, 3).run();
+    assert_eq!(res.moves, Some(2));
+    assert_eq!(res.solutions.len(), 1);
+
+    let key = &res.solutions[0];
+    assert_eq!(key.mv.pmv, Square::a1.to(Square::a6));
+
+    board.apply(key.mv);
+    let mut defences = vec![];
+    board.moves(&mut defences);
+    assert_eq!(key.replies.len(), defences.len());
+    board.unapply(key.mv);
+    assert_mates(&mut board, &res.solutions, Color::White);
+
+    let search = res.search_result();
+    assert_eq!(search.best, Some(key.mv));
+    assert_eq!(mate_distance(search.score), Some(3));
+    assert_eq!(search.pv.len(), 3);
+}
+
+#[test]
+fn mate_search_selfmate() {
+    let board = parse_fen("k7/4P3/K7/PQ3p2/4q3/8/8/8 w - - 0 1").unwrap();
+    let res = MateSearch::new(board, Stipulation::Selfmate, 2).run();
+
+    assert_eq!(res.moves, Some(1));
+    assert_eq!(res.solutions.len(), 1);
+    let key = &res.solutions[0];
+    assert_eq!(key.mv.pmv, Square::b5.to(Square::b7));
+    assert_eq!(key.replies.len(), 1);
+    assert_eq!(key.replies[0].mv.pmv, Square::e4.to(Square::b7));
+    assert!(key.replies[0].replies.is_empty());
+}
+
+#[test]
+fn mate_search_helpmate() {
+    let mut board = parse_fen("7k/8/6K1/8/8/8/8/R7 b - - 0 1").unwrap();
+    let res = MateSearch::new(board.clone(), Stipulation::Helpmate, 1).run();
+
+    assert_eq!(res.moves, Some(1));
+    assert_eq!(
+        res.solutions[0]
+            .line()
+            .iter()
+            .map(|mv| mv.pmv)
+            .collect::<Vec<_>>(),
+        vec![Square::h8.to(Square::g8), Square::a1.to(Square::a8)]
+    );
+    assert_mates(&mut board, &res.solutions, Color::White);
+
+    let res = MateSearch::new(board, Stipulation::Direct, 2).run();
+    assert_eq!(res.moves, None);
+}
+
+#[test]
+fn mate_search_stop() {
+    let stop = CancellationToken::new();
+    stop.cancel();
+    let res = MateSearch::new(BitBoard::startpos(), Stipulation::Direct, 4)
+        .with_stop(stop)
+        .run();
+    assert_eq!(res.moves, None);
+    assert!(res.nodes < 2048);
+
+    let res = MateSearch::new(BitBoard::startpos(), Stipulation::Direct, 4)
+        .with_limit(Some(Duration::ZERO))
+        .run();
+    assert_eq!(res.moves, None);
+    assert!(res.nodes < 2048);
+}
+
+#[test]
+fn mate_result_saturates() {
+    let result = |moves| MateResult {
+        moves: Some(moves),
+        solutions: vec![],
+        nodes: 0,
+        time: Duration::ZERO,
+    };
+
+    let res = result(3).search_result();
+    assert_eq!((res.score, res.depth), (MATE - 5, 5));
+
+    let res = result(200).search_result();
+    assert_eq!((res.score, res.depth), (MATE - MAX_PLY as Score, u8::MAX));
+
+    let res = result(usize::MAX).search_result();
+    assert_eq!((res.score, res.depth), (MATE - MAX_PLY as Score, u8::MAX));
+}
+
+#[test]
+fn staged_moves_match_legal_moves() {
+    let mut rng = pi_rng();
+    let mut ordering = MoveOrdering::default();
+    let mut moves = vec![];
+
+    for _ in 0..100 {
+        let mut board = BitBoard::startpos();
+
+        for ply in 0..80 {
+            moves.clear();
+            board.moves(&mut moves);
+            let Some(mv) = moves.choose(&mut rng).copied() else {
+                break;
+            };
+
+            let table_move = moves.choose(&mut rng).map(|mv| mv.simplify());
+            if let Some(quiet) = moves.iter().find(|m| !is_tactical(**m)) {
+                ordering.cutoff(*quiet, ply, 3, &[]);
+            }
+
+            let staged = board
+                .staged_moves(&ordering, ply, table_move)
+                .collect::<Vec<_>>();
+
+            assert_eq!(
+                staged.first().map(|mv| mv.simplify()),
+                table_move,
+                "{}",
+                render_fen(&board)
+            );
+
+            let mut expected = moves.iter().map(|mv| mv.simplify()).collect::<Vec<_>>();
+            let mut found = staged.iter().map(|mv| mv.simplify()).collect::<Vec<_>>();
+            expected.sort();
+            found.sort();
+            assert_eq!(expected, found, "{}", render_fen(&board));
+
+            let rank = |mv: &ChessMove| match (is_tactical(*mv), board.see(*mv) >= 0) {
+                (true, true) => 0,
+                (false, _) => 1,
+                (true, false) => 2,
+            };
+            assert!(staged[1..].is_sorted_by_key(rank), "{}", render_fen(&board));
+
+            board.apply(mv);
+        }
+    }
+}
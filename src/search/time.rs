@@ -0,0 +1,83 @@
+use std::time::Duration;
+
+use crate::{
+    model::Color,
+    notation::uci::gui::{GoCommand, TimeControl},
+    search::{MAX_PLY, SearchLimits},
+};
+
+/// Safety margin for communication lag
+pub const MOVE_OVERHEAD: Duration = Duration::from_millis(30);
+
+/// Assumed number of moves left when the GUI doesn't send `movestogo`
+pub const MOVES_TO_GO: u64 = 30;
+
+impl SearchLimits {
+    /// Limits for a UCI `go` command; `to_move` picks the clock to budget from
+    pub fn from_go(go: &GoCommand, to_move: Color) -> Self {
+        match go {
+            GoCommand::Time(tc) => Self::from_clock(tc, to_move),
+            GoCommand::Depth(n) => Self::depth((*n).clamp(1, MAX_PLY as u64 - 1) as u8),
+            GoCommand::Nodes(n) => Self::nodes(*n),
+            GoCommand::Mate(n) => Self::depth((2 * *n).clamp(2, MAX_PLY as u64) as u8 - 1),
+            GoCommand::Movetime(ms) => {
+                let t = Duration::from_millis(*ms).saturating_sub(MOVE_OVERHEAD);
+                Self {
+                    soft: Some(t),
+                    hard: Some(t),
+                    ..
+                }
+            }
+            GoCommand::SearchMoves(_)
+            | GoCommand::Ponder()
+            | GoCommand::Infinite()
+            | GoCommand::Perft(_) => Self::default(),
+        }
+    }
+
+    /// Combines all the parameters of one `go` line, the tightest limit winning
+    pub fn from_go_all(go: &[GoCommand], to_move: Color) -> Self {
+        let mut res = Self::default();
+        for limits in go.iter().map(|go| Self::from_go(go, to_move)) {
+            res.depth = res.depth.min(limits.depth);
+            res.nodes = res.nodes.min(limits.nodes);
+            res.soft = tighter(res.soft, limits.soft);
+            res.hard = tighter(res.hard, limits.hard);
+        }
+        res
+    }
+
+    /// The soft deadline stops iterative deepening from starting a new
+    /// depth, the hard deadline aborts the search outright
+    pub fn from_clock(tc: &TimeControl, to_move: Color) -> Self {
+        let (time, inc) = match to_move {
+            Color::White => (tc.wtime, tc.winc),
+            Color::Black => (tc.btime, tc.binc),
+        };
+
+        let moves_to_go = if tc.moves_to_go == 0 {
+            MOVES_TO_GO
+        } else {
+            tc.moves_to_go
+        };
+
+        let time = Duration::from_millis(time).saturating_sub(MOVE_OVERHEAD);
+        let inc = Duration::from_millis(inc);
+
+        let soft = (time / moves_to_go as u32 + inc * 3 / 4).min(time / 2);
+        let hard = (soft * 4).min(time * 3 / 4).max(soft);
+
+        Self {
+            soft: Some(soft),
+            hard: Some(hard),
+            ..
+        }
+    }
+}
+
+fn tighter(a: Option<Duration>, b: Option<Duration>) -> Option<Duration> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        _ => a.or(b),
+    }
+}
@@ -0,0 +1,68 @@
+pub mod retro;
+pub mod syzygy;
+pub mod tests;
+
+use strum::FromRepr;
+
+use crate::{
+    bits::board::BitBoard,
+    model::{DrawReason, Victory, WinReason, castling::CastlingRights},
+};
+
+/// Game-theoretic value for the side to move, where cursed wins and
+/// blessed losses are drawn by the fifty-move rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, FromRepr)]
+#[repr(i8)]
+pub enum Wdl {
+    Loss = -2,
+    BlessedLoss = -1,
+    Draw = 0,
+    CursedWin = 1,
+    Win = 2,
+}
+
+impl Wdl {
+    pub const fn opposite(self) -> Self {
+        match self {
+            Self::Loss => Self::Win,
+            Self::BlessedLoss => Self::CursedWin,
+            Self::Draw => Self::Draw,
+            Self::CursedWin => Self::BlessedLoss,
+            Self::Win => Self::Loss,
+        }
+    }
+}
+
+/// Plies to the next capture or pawn move, negative when losing
+pub type Dtz = i32;
+
+pub trait Tablebase {
+    /// The most pieces, kings included, of any position this can probe
+    fn max_pieces(&self) -> u32;
+
+    fn probe_wdl(&self, board: &BitBoard) -> Result<Wdl, String>;
+
+    fn probe_dtz(&self, board: &BitBoard) -> Result<Dtz, String>;
+
+    fn covers(&self, board: &BitBoard) -> bool {
+        board.metadata.castling_rights == CastlingRights::nil()
+            && (board.white.total | board.black.total).count_ones() <= self.max_pieces()
+    }
+}
+
+impl Victory {
+    /// The decided outcome of a position covered by `tb`
+    pub fn adjudicate(board: &BitBoard, tb: &dyn Tablebase) -> Option<Self> {
+        if !tb.covers(board) {
+            return None;
+        }
+
+        Some(match tb.probe_wdl(board).ok()? {
+            Wdl::Win => Self::from_color(board.metadata.to_move, WinReason::Adjudication),
+            Wdl::Loss => {
+                Self::from_color(board.metadata.to_move.opposite(), WinReason::Adjudication)
+            }
+            _ => Self::Draw(DrawReason::Adjudication),
+        })
+    }
+}
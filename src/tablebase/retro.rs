@@ -0,0 +1,896 @@
+use std::{
+    collections::BTreeMap,
+    io::{Read, Write},
+    path::Path,
+};
+
+use libflate::deflate::{Decoder, Encoder};
+
+use crate::{
+    bits::{
+        Squares,
+        attacks::pawn_attacks,
+        board::{BitBoard, HalfBitBoard},
+        exchange::piece_value,
+        unmoves::UnMove,
+    },
+    model::{
+        BoardRank, ChessPiece, Color, Square,
+        castling::CastlingRights,
+        metadata::Metadata,
+        moves::{ChessMove, SpecialMove},
+    },
+    tablebase::{Dtz, Tablebase, Wdl, syzygy::PIECE_ORDER},
+};
+
+pub const MAGIC: &[u8; 4] = b"MMTB";
+pub const VERSION: u8 = 2;
+pub const MAX_PIECES: usize = 4;
+
+/// Plies to mate with best play, for the side to move
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Dtm {
+    Win(u16),
+    Loss(u16),
+    Draw,
+}
+
+impl Dtm {
+    /// The value for the side that moved into a position worth `self`
+    pub const fn before(self) -> Self {
+        match self {
+            Self::Win(n) => Self::Loss(n + 1),
+            Self::Loss(n) => Self::Win(n + 1),
+            Self::Draw => Self::Draw,
+        }
+    }
+
+    pub const fn wdl(self) -> Wdl {
+        match self {
+            Self::Win(_) => Wdl::Win,
+            Self::Loss(_) => Wdl::Loss,
+            Self::Draw => Wdl::Draw,
+        }
+    }
+
+    /// Zero is a draw, `1..=128` a win in `2v - 1` plies
+    /// and `129..=255` a loss in `2(v - 129)` plies
+    const fn encode(self) -> Option<u8> {
+        match self {
+            Self::Draw => Some(0),
+            Self::Win(n) if n % 2 == 1 && n <= 255 => Some(n.div_ceil(2) as u8),
+            Self::Loss(n) if n % 2 == 0 && n <= 252 => Some(129 + (n / 2) as u8),
+            _ => None,
+        }
+    }
+
+    const fn decode(v: u8) -> Self {
+        match v {
+            0 => Self::Draw,
+            1..=128 => Self::Win(2 * v as u16 - 1),
+            _ => Self::Loss(2 * (v as u16 - 129)),
+        }
+    }
+}
+
+/// The pieces of each side, kings first, in Syzygy order
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Material {
+    pub white: Vec<ChessPiece>,
+    pub black: Vec<ChessPiece>,
+}
+
+impl Material {
+    /// Parses keys like `KBNvK`
+    pub fn parse(key: &str) -> Result<Self, String> {
+        let side = |s: &str| -> Result<Vec<ChessPiece>, String> {
+            let mut res = s
+                .chars()
+                .map(|c| {
+                    ChessPiece::parse(&c.to_string())
+                        .filter(|_| c.is_ascii_uppercase())
+                        .ok_or_else(|| format!("Bad piece {c} in {key}"))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            if res.iter().filter(|p| **p == ChessPiece::King).count() != 1 {
+                return Err(format!("{key} needs one king per side"));
+            }
+            res.sort_by_key(|p| PIECE_ORDER.iter().position(|q| q == p));
+            Ok(res)
+        };
+
+        let (white, black) = key
+            .split_once('v')
+            .ok_or_else(|| format!("{key} is not a material key"))?;
+
+        Ok(Self {
+            white: side(white)?,
+            black: side(black)?,
+        })
+    }
+
+    pub fn of(board: &BitBoard) -> Self {
+        let side = |half: &HalfBitBoard| {
+            PIECE_ORDER
+                .iter()
+                .flat_map(|p| std::iter::repeat_n(*p, half.mask(*p).count_ones() as usize))
+                .collect()
+        };
+        Self {
+            white: side(&board.white),
+            black: side(&board.black),
+        }
+    }
+
+    pub fn key(&self) -> String {
+        let side = |ps: &[ChessPiece]| ps.iter().map(|p| p.letter()).collect::<String>();
+        format!("{}v{}", side(&self.white), side(&self.black))
+    }
+
+    pub fn mirror(&self) -> Self {
+        Self {
+            white: self.black.clone(),
+            black: self.white.clone(),
+        }
+    }
+
+    /// The colouring tables are generated for, with the stronger side as white
+    pub fn canonical(self) -> Self {
+        let strength = |ps: &[ChessPiece]| {
+            let value = ps.iter().map(|p| piece_value(*p) as i32).sum::<i32>();
+            (
+                value,
+                ps.len(),
+                ps.iter().map(|p| -(*p as i8)).collect::<Vec<_>>(),
+            )
+        };
+        if strength(&self.black) > strength(&self.white) {
+            self.mirror()
+        } else {
+            self
+        }
+    }
+
+    pub fn pieces(&self) -> usize {
+        self.white.len() + self.black.len()
+    }
+
+    pub fn bare_kings(&self) -> bool {
+        self.pieces() == 2
+    }
+
+    /// Every material a capture or promotion can lead to
+    fn successors(&self) -> Vec<Material> {
+        let mut res = vec![];
+        for white in [true, false] {
+            let (side, other) = if white {
+                (&self.white, &self.black)
+            } else {
+                (&self.black, &self.white)
+            };
+
+            for (i, p) in side.iter().enumerate() {
+                if *p == ChessPiece::King {
+                    continue;
+                }
+
+                let mut fewer = side.clone();
+                fewer.remove(i);
+                res.push(Self::with(!white, other.clone(), fewer));
+
+                if *p != ChessPiece::Pawn {
+                    continue;
+                }
+
+                for promo in
+                    [ChessPiece::Queen, ChessPiece::Rook, ChessPiece::Bishop, ChessPiece::Knight]
+                {
+                    let mut promoted = side.clone();
+                    promoted[i] = promo;
+                    res.push(Self::with(white, promoted.clone(), other.clone()));
+
+                    for (j, q) in other.iter().enumerate() {
+                        if *q != ChessPiece::King {
+                            let mut captured = other.clone();
+                            captured.remove(j);
+                            res.push(Self::with(white, promoted.clone(), captured));
+                        }
+                    }
+                }
+            }
+        }
+        res
+    }
+
+    /// Material with `side` as white if `white` is set, otherwise as black
+    fn with(white: bool, mut side: Vec<ChessPiece>, mut other: Vec<ChessPiece>) -> Self {
+        side.sort_by_key(|p| PIECE_ORDER.iter().position(|q| q == p));
+        other.sort_by_key(|p| PIECE_ORDER.iter().position(|q| q == p));
+        if white {
+            Self {
+                white: side,
+                black: other,
+            }
+        } else {
+            Self {
+                white: other,
+                black: side,
+            }
+        }
+    }
+
+    /// Piece kinds and colours in index order, white first
+    fn slots(&self) -> impl Iterator<Item = (Color, ChessPiece)> {
+        self.white
+            .iter()
+            .map(|p| (Color::White, *p))
+            .chain(self.black.iter().map(|p| (Color::Black, *p)))
+    }
+}
+
+/// Distances to mate for one material, one byte per position as encoded by
+/// `Dtm`. See `Layout` for the indexing; unused indices hold draws.
+///
+/// The file format is the magic `MMTB`, a version byte, the length and bytes
+/// of the material key, the entry count as a little-endian u32 and then the
+/// deflated entries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DtmTable {
+    pub material: Material,
+    pub entries: Vec<u8>,
+    layout: Layout,
+}
+
+impl DtmTable {
+    pub fn new(material: Material, entries: Vec<u8>) -> Result<Self, String> {
+        let layout = Layout::new(&material);
+        if entries.len() != layout.len() {
+            return Err(format!("Wrong entry count for {}", material.key()));
+        }
+        Ok(Self {
+            material,
+            entries,
+            layout,
+        })
+    }
+
+    pub fn probe(&self, board: &BitBoard) -> Result<Dtm, String> {
+        if Material::of(board) != self.material {
+            return Err(format!(
+                "Table {} cannot probe {}",
+                self.material.key(),
+                Material::of(board).key()
+            ));
+        }
+        let ix = self
+            .layout
+            .index(board)
+            .ok_or("The kings are next to each other")?;
+        Ok(Dtm::decode(self.entries[ix]))
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        let key = self.material.key();
+        let mut res = MAGIC.to_vec();
+        res.push(VERSION);
+        res.push(key.len() as u8);
+        res.extend(key.bytes());
+        res.extend((self.entries.len() as u32).to_le_bytes());
+
+        let mut encoder = Encoder::new(res);
+        encoder
+            .write_all(&self.entries)
+            .map_err(|e| e.to_string())?;
+        encoder.finish().into_result().map_err(|e| e.to_string())
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let Some((MAGIC, rest)) = bytes.split_first_chunk::<4>() else {
+            return Err("Not a tablebase file".to_string());
+        };
+        let Some(([VERSION, len], rest)) = rest.split_first_chunk::<2>() else {
+            return Err("Unsupported tablebase version".to_string());
+        };
+        let (key, rest) = rest
+            .split_at_checked(*len as usize)
+            .ok_or("Tablebase file is truncated")?;
+        let material = Material::parse(std::str::from_utf8(key).map_err(|e| e.to_string())?)?;
+        let (count, deflated) = rest
+            .split_first_chunk::<4>()
+            .ok_or("Tablebase file is truncated")?;
+
+        let mut entries = Vec::with_capacity(u32::from_le_bytes(*count) as usize);
+        Decoder::new(deflated)
+            .read_to_end(&mut entries)
+            .map_err(|e| format!("Corrupt entries for {}: {e}", material.key()))?;
+        if entries.len() != u32::from_le_bytes(*count) as usize {
+            return Err(format!("Wrong entry count for {}", material.key()));
+        }
+
+        Self::new(material, entries)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        std::fs::write(path, self.to_bytes()?).map_err(|e| e.to_string())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        Self::from_bytes(&std::fs::read(path).map_err(|e| e.to_string())?)
+    }
+}
+
+/// Distance-to-mate tables generated by retrograde analysis
+#[derive(Debug, Clone, Default)]
+pub struct RetroTablebase {
+    pub tables: BTreeMap<String, DtmTable>,
+}
+
+impl RetroTablebase {
+    pub fn insert(&mut self, table: DtmTable) {
+        self.tables.insert(table.material.key(), table);
+    }
+
+    /// Generates the table for `key` along with every table it depends on
+    pub fn generate(&mut self, key: &str) -> Result<&DtmTable, String> {
+        let material = Material::parse(key)?.canonical();
+        self.generate_material(&material)?;
+        Ok(&self.tables[&material.key()])
+    }
+
+    fn generate_material(&mut self, material: &Material) -> Result<(), String> {
+        if material.bare_kings() || self.tables.contains_key(&material.key()) {
+            return Ok(());
+        }
+
+        if material.pieces() > MAX_PIECES {
+            return Err(format!(
+                "{} has more than {MAX_PIECES} pieces",
+                material.key()
+            ));
+        }
+
+        for sub in material.successors() {
+            self.generate_material(&sub.canonical())?;
+        }
+
+        let table = self.build(material.clone())?;
+        self.insert(table);
+        Ok(())
+    }
+
+    pub fn probe_dtm(&self, board: &BitBoard) -> Result<Dtm, String> {
+        if board.metadata.castling_rights != CastlingRights::nil() {
+            return Err("Positions with castling rights are not covered".to_string());
+        }
+
+        let material = Material::of(board);
+        if material.bare_kings() {
+            return Ok(Dtm::Draw);
+        }
+
+        if let Some(table) = self.tables.get(&material.key()) {
+            table.probe(board)
+        } else if let Some(table) = self.tables.get(&material.mirror().key()) {
+            table.probe(&mirror(board))
+        } else {
+            Err(format!("No table for {}", material.key()))
+        }
+    }
+
+    /// Counts down the distinct quiet successors of every position, resolving
+    /// it once a successor loses or the last one turns out to win
+    fn build(&self, material: Material) -> Result<DtmTable, String> {
+        let layout = Layout::new(&material);
+        let len = layout.len();
+        let mut entries = vec![0; len];
+        let mut done = vec![false; len];
+        let mut remaining = vec![0u8; len];
+        let mut buckets = Buckets(vec![]);
+
+        let mut moves = vec![];
+        let mut quiet = vec![];
+        for ix in 0..len {
+            let Some(board) = layout.decode(ix) else {
+                done[ix] = true;
+                continue;
+            };
+
+            let exit = self.exit(&layout, &board, &mut moves, &mut quiet)?;
+            if moves.is_empty() {
+                if board.in_check() {
+                    resolve(&mut entries, &mut done, ix, Dtm::Loss(0))?;
+                    buckets.push(0, ix, false);
+                } else {
+                    done[ix] = true;
+                }
+                continue;
+            }
+
+            remaining[ix] = quiet.len() as u8;
+            if let Some(n) = exit.win {
+                buckets.push(n, ix, true);
+            } else if remaining[ix] == 0 {
+                if exit.draw {
+                    done[ix] = true;
+                } else {
+                    resolve(&mut entries, &mut done, ix, Dtm::Loss(exit.loss))?;
+                    buckets.push(exit.loss, ix, false);
+                }
+            }
+        }
+
+        let mut unmoves = vec![];
+        let mut prevs = vec![];
+        let mut ply = 0;
+        while let Some(bucket) = buckets.0.get_mut(ply as usize) {
+            for (ix, seed) in std::mem::take(bucket) {
+                let ix = ix as usize;
+                if seed {
+                    if done[ix] {
+                        continue;
+                    }
+                    resolve(&mut entries, &mut done, ix, Dtm::Win(ply))?;
+                }
+
+                let win = matches!(Dtm::decode(entries[ix]), Dtm::Win(_));
+                let board = layout.decode(ix).unwrap();
+                layout.predecessors(&board, &mut unmoves, &mut prevs);
+
+                for &prev in &prevs {
+                    if done[prev] {
+                        continue;
+                    }
+
+                    if !win {
+                        resolve(&mut entries, &mut done, prev, Dtm::Win(ply + 1))?;
+                        buckets.push(ply + 1, prev, false);
+                        continue;
+                    }
+
+                    remaining[prev] = remaining[prev]
+                        .checked_sub(1)
+                        .ok_or("More predecessors than successors")?;
+                    if remaining[prev] != 0 {
+                        continue;
+                    }
+
+                    let before = layout.decode(prev).unwrap();
+                    let exit = self.exit(&layout, &before, &mut moves, &mut quiet)?;
+                    if exit.win.is_none() && !exit.draw {
+                        let n = (ply + 1).max(exit.loss);
+                        resolve(&mut entries, &mut done, prev, Dtm::Loss(n))?;
+                        buckets.push(n, prev, false);
+                    }
+                }
+            }
+            ply += 1;
+        }
+
+        Ok(DtmTable {
+            material,
+            entries,
+            layout,
+        })
+    }
+
+    /// Fills `moves` and the sorted indices of the distinct quiet successors,
+    /// probing the tables the other moves lead to
+    fn exit(
+        &self,
+        layout: &Layout,
+        board: &BitBoard,
+        moves: &mut Vec<ChessMove>,
+        quiet: &mut Vec<usize>,
+    ) -> Result<Exit, String> {
+        moves.clear();
+        quiet.clear();
+        board.moves(moves);
+
+        let mut exit = Exit::default();
+        for mv in moves.iter() {
+            let mut after = board.clone();
+            after.apply(*mv);
+
+            if mv.cpc.capture().is_none() && !matches!(mv.spc, Some(SpecialMove::Promotion(_))) {
+                quiet.push(layout.index(&after).unwrap());
+                continue;
+            }
+
+            match self.probe_dtm(&after)?.before() {
+                Dtm::Win(n) => exit.win = Some(exit.win.map_or(n, |w| w.min(n))),
+                Dtm::Loss(n) => exit.loss = exit.loss.max(n),
+                Dtm::Draw => exit.draw = true,
+            }
+        }
+
+        quiet.sort_unstable();
+        quiet.dedup();
+        Ok(exit)
+    }
+}
+
+impl Tablebase for RetroTablebase {
+    fn max_pieces(&self) -> u32 {
+        self.tables
+            .values()
+            .map(|t| t.material.pieces() as u32)
+            .max()
+            .unwrap_or(2)
+    }
+
+    fn probe_wdl(&self, board: &BitBoard) -> Result<Wdl, String> {
+        self.probe_dtm(board).map(Dtm::wdl)
+    }
+
+    fn probe_dtz(&self, _: &BitBoard) -> Result<Dtz, String> {
+        Err("Retrograde tables only store distance to mate".to_string())
+    }
+
+    fn covers(&self, board: &BitBoard) -> bool {
+        board.metadata.castling_rights == CastlingRights::nil()
+            && (Material::of(board).bare_kings()
+                || self.tables.contains_key(&Material::of(board).key())
+                || self
+                    .tables
+                    .contains_key(&Material::of(board).mirror().key()))
+    }
+}
+
+fn resolve(entries: &mut [u8], done: &mut [bool], ix: usize, dtm: Dtm) -> Result<(), String> {
+    done[ix] = true;
+    entries[ix] = dtm
+        .encode()
+        .ok_or_else(|| format!("{dtm:?} is too long to store"))?;
+    Ok(())
+}
+
+/// The best outcomes of the captures and promotions leaving a table
+#[derive(Debug, Clone, Copy, Default)]
+struct Exit {
+    win: Option<u16>,
+    loss: u16,
+    draw: bool,
+}
+
+/// Positions to resolve by ply, flagged when only a capture or
+/// promotion is known to win so far
+struct Buckets(Vec<Vec<(u32, bool)>>);
+
+impl Buckets {
+    fn push(&mut self, ply: u16, ix: usize, seed: bool) {
+        let ply = ply as usize;
+        if self.0.len() <= ply {
+            self.0.resize_with(ply + 1, Vec::new);
+        }
+        self.0[ply].push((ix as u32, seed));
+    }
+}
+
+/// Pieces of one colour and kind besides the kings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Group {
+    color: Color,
+    piece: ChessPiece,
+    count: usize,
+}
+
+impl Group {
+    /// Pawns take one of 48 squares, other pieces one of the 62 the kings leave
+    const fn squares(self) -> usize {
+        match self.piece {
+            ChessPiece::Pawn => 48,
+            _ => 62,
+        }
+    }
+
+    /// Each unordered set of distinct squares
+    const fn size(self) -> usize {
+        match self.count {
+            1 => self.squares(),
+            _ => self.squares() * (self.squares() - 1) / 2,
+        }
+    }
+}
+
+const NO_KINGS: u16 = u16::MAX;
+
+/// Indexes positions up to symmetry. The kings take one of the pairs left
+/// after folding the board onto a1-d1-d4 (or files a-d with pawns), each group
+/// of identical pieces takes a combination of its squares, and the side to move
+/// and en passant come last. Of the symmetric images of a position, the
+/// lowest index is the one stored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Layout {
+    /// Only the left-right mirror keeps pawns moving the same way
+    symmetries: u8,
+    /// Whether a pawn capture en passant can stay inside the table
+    en_passant: bool,
+    kings: Vec<(Square, Square)>,
+    /// The position of each king pair in `kings`, by `64 white + black`
+    king_ix: Vec<u16>,
+    groups: Vec<Group>,
+}
+
+impl Layout {
+    fn new(material: &Material) -> Self {
+        let pawns = |ps: &[ChessPiece]| ps.contains(&ChessPiece::Pawn);
+        let any_pawns = pawns(&material.white) || pawns(&material.black);
+
+        let mut kings = vec![];
+        let mut king_ix = vec![NO_KINGS; 64 * 64];
+        for (w, b) in (0..64i8).flat_map(|w| (0..64).map(move |b| (w, b))) {
+            let (wf, wr) = (w % 8, w / 8);
+            let (bf, br) = (b % 8, b / 8);
+            let canonical = if any_pawns {
+                wf < 4
+            } else {
+                wf < 4 && wr <= wf && (wr != wf || br <= bf)
+            };
+            if canonical && (wf - bf).abs().max((wr - br).abs()) > 1 {
+                king_ix[w as usize * 64 + b as usize] = kings.len() as u16;
+                kings.push((Square::new(w).unwrap(), Square::new(b).unwrap()));
+            }
+        }
+
+        let mut groups: Vec<Group> = vec![];
+        for (color, piece) in material.slots().filter(|(_, p)| *p != ChessPiece::King) {
+            match groups.last_mut() {
+                Some(g) if g.color == color && g.piece == piece => g.count += 1,
+                _ => groups.push(Group {
+                    color,
+                    piece,
+                    count: 1,
+                }),
+            }
+        }
+        debug_assert!(groups.iter().all(|g| g.count <= 2));
+
+        Self {
+            symmetries: if any_pawns { 2 } else { 8 },
+            en_passant: pawns(&material.white) && pawns(&material.black),
+            kings,
+            king_ix,
+            groups,
+        }
+    }
+
+    const fn sides(&self) -> usize {
+        if self.en_passant { 4 } else { 2 }
+    }
+
+    fn len(&self) -> usize {
+        self.kings.len() * self.groups.iter().map(|g| g.size()).product::<usize>() * self.sides()
+    }
+
+    /// The lowest index of the symmetric images of `board`, unless its kings touch
+    fn index(&self, board: &BitBoard) -> Option<usize> {
+        (0..self.symmetries)
+            .filter_map(|t| self.index_by(board, t))
+            .min()
+    }
+
+    fn index_by(&self, board: &BitBoard, t: u8) -> Option<usize> {
+        let king = |h: &HalfBitBoard| Squares(h.kings).next().map(|sq| transform(sq, t));
+        let (wk, bk) = (king(&board.white)?, king(&board.black)?);
+        let kk = self.king_ix[wk.ix() as usize * 64 + bk.ix() as usize];
+        if kk == NO_KINGS {
+            return None;
+        }
+
+        let mut res = kk as usize;
+        for g in &self.groups {
+            let (half, _) = board.active_passive(g.color);
+            let mut slots =
+                Squares(half.mask(g.piece)).map(|sq| slot(*g, transform(sq, t), wk, bk));
+            let a = slots.next()?;
+            let v = match slots.next() {
+                Some(b) => a.max(b) * (a.max(b) - 1) / 2 + a.min(b),
+                None => a,
+            };
+            res = res * g.size() + v;
+        }
+
+        let ep = self.en_passant && en_passant_capture(board);
+        let stm = (board.metadata.to_move == Color::Black) as usize;
+        Some(res * self.sides() + 2 * ep as usize + stm)
+    }
+
+    /// The legal position stored at `ix`, if any
+    fn decode(&self, ix: usize) -> Option<BitBoard> {
+        let mut rest = ix / self.sides();
+        let mut slots = [0; MAX_PIECES - 2];
+        for (g, v) in self.groups.iter().zip(&mut slots).rev() {
+            *v = rest % g.size();
+            rest /= g.size();
+        }
+        let (wk, bk) = *self.kings.get(rest)?;
+
+        let mut white = HalfBitBoard::empty();
+        let mut black = HalfBitBoard::empty();
+        white.kings = wk.bit();
+        black.kings = bk.bit();
+        white.total = wk.bit();
+        black.total = bk.bit();
+
+        for (g, v) in self.groups.iter().zip(slots) {
+            let squares = if g.count == 1 {
+                [Some(v), None]
+            } else {
+                let b = (1..).find(|b| b * (b + 1) / 2 > v).unwrap();
+                [Some(v - b * (b - 1) / 2), Some(b)]
+            };
+
+            for s in squares.into_iter().flatten() {
+                let sq = unslot(*g, s, wk, bk);
+                if (white.total | black.total) & sq.bit() != 0 {
+                    return None;
+                }
+                let half = match g.color {
+                    Color::White => &mut white,
+                    Color::Black => &mut black,
+                };
+                *half.piece(g.piece) |= sq.bit();
+                half.total |= sq.bit();
+            }
+        }
+
+        let to_move = if ix & 1 == 0 {
+            Color::White
+        } else {
+            Color::Black
+        };
+
+        let mut board = BitBoard {
+            white,
+            black,
+            metadata: Metadata {
+                to_move,
+                castling_rights: CastlingRights::nil(),
+                halfmove_clock: 0,
+                hash: 0,
+                ..
+            },
+        };
+
+        if self.en_passant && ix & 2 != 0 {
+            board.metadata.en_passant = Some(double_push(&board)?);
+        }
+
+        let (active, passive) = board.active_passive(to_move);
+        if active.attacks(to_move, passive.total) & passive.kings != 0
+            || self.index(&board) != Some(ix)
+        {
+            return None;
+        }
+
+        Some(board)
+    }
+
+    /// The distinct indices of the positions one quiet move before `board`,
+    /// with and without en passant where either could have been the case
+    fn predecessors(&self, board: &BitBoard, unmoves: &mut Vec<UnMove>, res: &mut Vec<usize>) {
+        unmoves.clear();
+        res.clear();
+        board.quiet_unmoves(unmoves);
+
+        for um in unmoves.iter() {
+            let double =
+                um.piece == ChessPiece::Pawn && um.pmv.from.ix().abs_diff(um.pmv.to.ix()) == 16;
+            if self.en_passant && double {
+                let mut pushed = board.clone();
+                pushed.metadata.en_passant = Square::new((um.pmv.from.ix() + um.pmv.to.ix()) / 2);
+                if en_passant_capture(&pushed) != board.metadata.en_passant.is_some() {
+                    continue;
+                }
+            } else if board.metadata.en_passant.is_some() {
+                continue;
+            }
+
+            let mut prev = board.retract(*um);
+            res.extend(self.index(&prev));
+            if self.en_passant
+                && let Some(sq) = double_push(&prev)
+            {
+                prev.metadata.en_passant = Some(sq);
+                res.extend(self.index(&prev));
+            }
+        }
+
+        res.sort_unstable();
+        res.dedup();
+    }
+}
+
+/// One of the eight symmetries of the board: bit 0 mirrors the files,
+/// bit 1 the ranks and bit 2 the a1-h8 diagonal, which is applied first
+fn transform(sq: Square, t: u8) -> Square {
+    let mut ix = sq.ix();
+    if t & 4 != 0 {
+        ix = (ix >> 3) | ((ix & 7) << 3);
+    }
+    if t & 1 != 0 {
+        ix ^= 7;
+    }
+    if t & 2 != 0 {
+        ix ^= 56;
+    }
+    Square::new(ix).unwrap()
+}
+
+fn slot(g: Group, sq: Square, wk: Square, bk: Square) -> usize {
+    let ix = sq.ix() as usize;
+    match g.piece {
+        ChessPiece::Pawn => ix - 8,
+        _ => ix - (wk.ix() < sq.ix()) as usize - (bk.ix() < sq.ix()) as usize,
+    }
+}
+
+fn unslot(g: Group, s: usize, wk: Square, bk: Square) -> Square {
+    let mut ix = s as i8;
+    if g.piece == ChessPiece::Pawn {
+        ix += 8;
+    } else {
+        let (lo, hi) = (wk.ix().min(bk.ix()), wk.ix().max(bk.ix()));
+        if ix >= lo {
+            ix += 1;
+        }
+        if ix >= hi {
+            ix += 1;
+        }
+    }
+    Square::new(ix).unwrap()
+}
+
+/// Whether the side to move has a pawn on the en passant square's diagonals
+fn en_passant_capture(board: &BitBoard) -> bool {
+    let to_move = board.metadata.to_move;
+    let (active, _) = board.active_passive(to_move);
+    board
+        .metadata
+        .en_passant
+        .is_some_and(|sq| pawn_attacks(active.pawns, to_move) & sq.bit() != 0)
+}
+
+/// The square a pawn of the side that just moved passed over, if it can have
+/// pushed two squares and can be taken en passant
+fn double_push(board: &BitBoard) -> Option<Square> {
+    let to_move = board.metadata.to_move;
+    let (_, passive) = board.active_passive(to_move);
+    let (rank, step) = match to_move {
+        Color::White => (BoardRank::_5, 8),
+        Color::Black => (BoardRank::_4, -8),
+    };
+
+    let pawn = Squares(passive.pawns & rank.mask()).next()?;
+    let passed = Square::new(pawn.ix() + step)?;
+    let start = Square::new(pawn.ix() + 2 * step)?;
+    let mut pushed = board.clone();
+    pushed.metadata.en_passant = Some(passed);
+
+    ((board.white.total | board.black.total) & (passed.bit() | start.bit()) == 0
+        && en_passant_capture(&pushed))
+    .then_some(passed)
+}
+
+/// The same position with the colours swapped and the board flipped
+fn mirror(board: &BitBoard) -> BitBoard {
+    let flip = |h: &HalfBitBoard| HalfBitBoard {
+        pawns: h.pawns.swap_bytes(),
+        knights: h.knights.swap_bytes(),
+        bishops: h.bishops.swap_bytes(),
+        rooks: h.rooks.swap_bytes(),
+        queens: h.queens.swap_bytes(),
+        kings: h.kings.swap_bytes(),
+        total: h.total.swap_bytes(),
+    };
+
+    BitBoard {
+        white: flip(&board.black),
+        black: flip(&board.white),
+        metadata: Metadata {
+            to_move: board.metadata.to_move.opposite(),
+            en_passant: board.metadata.en_passant.map(Square::swap),
+            ..board.metadata
+        },
+    }
+}
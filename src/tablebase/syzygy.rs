@@ -0,0 +1,955 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+    sync::{LazyLock, OnceLock},
+};
+
+use strum::VariantArray;
+
+use crate::{
+    bits::board::{BitBoard, HalfBitBoard},
+    model::{ChessPiece, Color, castling::CastlingRights},
+    tablebase::{Dtz, Tablebase, Wdl},
+};
+
+pub const WDL_MAGIC: [u8; 4] = [0x71, 0xe8, 0x23, 0x5d];
+pub const DTZ_MAGIC: [u8; 4] = [0xd7, 0x66, 0x0c, 0xa5];
+
+pub const PIECE_ORDER: [ChessPiece; 6] = [
+    ChessPiece::King,
+    ChessPiece::Queen,
+    ChessPiece::Rook,
+    ChessPiece::Bishop,
+    ChessPiece::Knight,
+    ChessPiece::Pawn,
+];
+
+/// The WDL (`.rtbw`) and DTZ (`.rtbz`) files found in a directory,
+/// keyed by material such as `KQvK`. Each file is read on its first probe.
+#[derive(Debug, Clone, Default)]
+pub struct Syzygy {
+    pub wdl: BTreeMap<String, PathBuf>,
+    pub dtz: BTreeMap<String, PathBuf>,
+    pub max_pieces: u32,
+    loaded: BTreeMap<PathBuf, OnceLock<Result<Table, String>>>,
+}
+
+impl Syzygy {
+    /// Indexes every table in `dir`, rejecting files with a bad header
+    pub fn open(dir: &Path) -> Result<Self, String> {
+        let mut res = Self::default();
+
+        for entry in std::fs::read_dir(dir).map_err(|e| format!("{}: {e}", dir.display()))? {
+            let path = entry.map_err(|e| e.to_string())?.path();
+
+            let (Some(stem), Some(ext)) = (
+                path.file_stem().and_then(|s| s.to_str()),
+                path.extension().and_then(|s| s.to_str()),
+            ) else {
+                continue;
+            };
+
+            let (magic, tables) = match ext {
+                "rtbw" => (WDL_MAGIC, &mut res.wdl),
+                "rtbz" => (DTZ_MAGIC, &mut res.dtz),
+                _ => continue,
+            };
+
+            let pieces = parse_material(stem)
+                .ok_or_else(|| format!("{}: not a Syzygy table name", path.display()))?;
+
+            let mut header = [0u8; 4];
+            File::open(&path)
+                .and_then(|mut f| f.read_exact(&mut header))
+                .map_err(|e| format!("{}: {e}", path.display()))?;
+            if header != magic {
+                return Err(format!("{}: bad Syzygy magic", path.display()));
+            }
+
+            res.max_pieces = res.max_pieces.max(pieces);
+            res.loaded.insert(path.clone(), OnceLock::new());
+            tables.insert(stem.to_string(), path);
+        }
+
+        Ok(res)
+    }
+
+    /// The WDL file for `board`, whose material may have the colors swapped
+    pub fn wdl_path(&self, board: &BitBoard) -> Result<&Path, String> {
+        self.path(&self.wdl, board)
+    }
+
+    /// The DTZ file for `board`, whose material may have the colors swapped
+    pub fn dtz_path(&self, board: &BitBoard) -> Result<&Path, String> {
+        self.path(&self.dtz, board)
+    }
+
+    fn path<'a>(
+        &self,
+        tables: &'a BTreeMap<String, PathBuf>,
+        board: &BitBoard,
+    ) -> Result<&'a Path, String> {
+        if !self.covers(board) {
+            return Err("Position is not covered by the tablebase".to_string());
+        }
+
+        let key = material_key(board);
+        let (white, black) = key.split_once('v').unwrap_or_default();
+        tables
+            .get(&key)
+            .or_else(|| tables.get(&format!("{black}v{white}")))
+            .map(PathBuf::as_path)
+            .ok_or_else(|| format!("No Syzygy table for {key}"))
+    }
+
+    fn table(&self, path: &Path, dtz: bool) -> Result<&Table, String> {
+        let stem = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or_default();
+        self.loaded
+            .get(path)
+            .ok_or_else(|| format!("{}: not indexed", path.display()))?
+            .get_or_init(|| {
+                let bytes = std::fs::read(path).map_err(|e| format!("{}: {e}", path.display()))?;
+                Table::new(stem, bytes, dtz).map_err(|e| format!("{}: {e}", path.display()))
+            })
+            .as_ref()
+            .map_err(Clone::clone)
+    }
+
+    /// The stored outcome, ignoring en passant and the zeroing moves `search` tries first
+    fn table_wdl(&self, board: &BitBoard) -> Result<Wdl, String> {
+        if (board.white.total | board.black.total).count_ones() == 2 {
+            return Ok(Wdl::Draw);
+        }
+
+        let table = self.table(self.wdl_path(board)?, false)?;
+        let value = table.value(board, Wdl::Draw).unwrap_or(2);
+        Wdl::from_repr(value as i8 - 2).ok_or_else(|| format!("Bad WDL value {value}"))
+    }
+
+    /// The stored distance to zeroing, or `None` when the table holds the other side to move
+    fn table_dtz(&self, board: &BitBoard, wdl: Wdl) -> Result<Option<Dtz>, String> {
+        let table = self.table(self.dtz_path(board)?, true)?;
+        Ok(table.value(board, wdl))
+    }
+
+    /// The outcome after trying captures, and pawn moves when `pawns` is set,
+    /// since tables may store anything where those are best. The flag says
+    /// whether the best move zeroes the fifty-move counter.
+    fn search(&self, board: &BitBoard, pawns: bool) -> Result<(Wdl, bool), String> {
+        let mut moves = vec![];
+        board.moves(&mut moves);
+
+        let mut best = Wdl::Loss;
+        let mut tried = 0;
+        for &mv in &moves {
+            if mv.cap.is_none() && !(pawns && mv.cpc.piece() == ChessPiece::Pawn) {
+                continue;
+            }
+
+            tried += 1;
+            let mut next = board.clone();
+            next.apply(mv);
+            let wdl = self.search(&next, false)?.0.opposite();
+            if wdl == Wdl::Win {
+                return Ok((wdl, true));
+            }
+            best = best.max(wdl);
+        }
+
+        let exhausted = tried > 0 && tried == moves.len();
+        let stored = if exhausted {
+            best
+        } else {
+            self.table_wdl(board)?
+        };
+
+        if best >= stored {
+            Ok((best, best > Wdl::Draw || exhausted))
+        } else {
+            Ok((stored, false))
+        }
+    }
+}
+
+impl Tablebase for Syzygy {
+    fn max_pieces(&self) -> u32 {
+        self.max_pieces
+    }
+
+    fn probe_wdl(&self, board: &BitBoard) -> Result<Wdl, String> {
+        if !self.covers(board) {
+            return Err("Position is not covered by the tablebase".to_string());
+        }
+        Ok(self.search(board, false)?.0)
+    }
+
+    fn probe_dtz(&self, board: &BitBoard) -> Result<Dtz, String> {
+        if !self.covers(board) {
+            return Err("Position is not covered by the tablebase".to_string());
+        }
+
+        let (wdl, zeroing) = self.search(board, true)?;
+        if wdl == Wdl::Draw {
+            return Ok(0);
+        }
+        if zeroing {
+            return Ok(before_zeroing(wdl));
+        }
+
+        if let Some(dtz) = self.table_dtz(board, wdl)? {
+            let cursed = matches!(wdl, Wdl::CursedWin | Wdl::BlessedLoss);
+            return Ok((dtz + 100 * cursed as Dtz) * (wdl as Dtz).signum());
+        }
+
+        // The table only has the other side to move, so look one ply ahead
+        let mut moves = vec![];
+        board.moves(&mut moves);
+
+        let mut best = None;
+        for mv in moves {
+            let mut next = board.clone();
+            next.apply(mv);
+
+            let mut dtz = if mv.irreversible() {
+                -before_zeroing(self.search(&next, false)?.0)
+            } else {
+                -self.probe_dtz(&next)?
+            };
+
+            // A mate is as short as it gets
+            let mut replies = vec![];
+            next.moves(&mut replies);
+            if dtz == 1 && replies.is_empty() && next.in_check() {
+                best = Some(1);
+            }
+
+            if !mv.irreversible() {
+                dtz += dtz.signum();
+            }
+
+            if dtz.signum() == (wdl as Dtz).signum() && best.is_none_or(|b| dtz < b) {
+                best = Some(dtz);
+            }
+        }
+
+        Ok(best.unwrap_or(-1))
+    }
+
+    /// Also requires a table for the material on the board
+    fn covers(&self, board: &BitBoard) -> bool {
+        let pieces = (board.white.total | board.black.total).count_ones();
+        let key = material_key(board);
+        let (white, black) = key.split_once('v').unwrap_or_default();
+
+        board.metadata.castling_rights == CastlingRights::nil()
+            && pieces <= self.max_pieces
+            && (pieces == 2
+                || self.wdl.contains_key(&key)
+                || self.wdl.contains_key(&format!("{black}v{white}")))
+    }
+}
+
+/// The distance of a position whose best move zeroes the counter
+fn before_zeroing(wdl: Wdl) -> Dtz {
+    match wdl {
+        Wdl::Win => 1,
+        Wdl::CursedWin => 101,
+        Wdl::Draw => 0,
+        Wdl::BlessedLoss => -101,
+        Wdl::Loss => -1,
+    }
+}
+
+/// Syzygy's name for the material on the board, white first, like `KRPvKR`
+pub fn material_key(board: &BitBoard) -> String {
+    format!("{}v{}", side_key(&board.white), side_key(&board.black))
+}
+
+fn side_key(half: &HalfBitBoard) -> String {
+    PIECE_ORDER
+        .iter()
+        .flat_map(|p| std::iter::repeat_n(p.letter(), half.mask(*p).count_ones() as usize))
+        .collect()
+}
+
+/// The number of pieces in a table name, if it is well-formed
+fn parse_material(key: &str) -> Option<u32> {
+    let (white, black) = key.split_once('v')?;
+    let mut count = 0;
+
+    for side in [white, black] {
+        let mut order = 1;
+        for c in side.strip_prefix('K')?.chars() {
+            let ix = PIECE_ORDER.iter().position(|p| p.letter() == c)?;
+            if ix < order {
+                return None;
+            }
+            order = ix;
+        }
+        count += side.len() as u32;
+    }
+
+    Some(count)
+}
+
+/// Layout flags of a file
+const HAS_PAWNS: u8 = 2;
+
+/// Flags of one subtable
+const STM: u8 = 1;
+const MAPPED: u8 = 2;
+const WIN_PLIES: u8 = 4;
+const LOSS_PLIES: u8 = 8;
+const WIDE: u8 = 16;
+const SINGLE_VALUE: u8 = 128;
+
+/// How many ways `k` of `n` squares can be chosen
+const fn binomial(n: u64, k: u64) -> u64 {
+    if k > n {
+        return 0;
+    }
+    let mut res = 1;
+    let mut i = 0;
+    while i < k {
+        res = res * (n - i) / (i + 1);
+        i += 1;
+    }
+    res
+}
+
+/// Rank minus file, so negative below the a1-h8 diagonal
+const fn off_diagonal(sq: usize) -> i32 {
+    (sq >> 3) as i32 - (sq & 7) as i32
+}
+
+/// Lookups for turning squares into table indices
+struct Indices {
+    /// The a1-d1-d4 triangle, diagonal last
+    triangle: [u64; 64],
+    /// Below the a1-h8 diagonal, diagonal last
+    lower: [u64; 64],
+    /// The 462 king pairs with the first king in the triangle
+    kings: [[u64; 64]; 10],
+    /// How many squares other pawns have with the leading pawn here
+    pawns: [u64; 64],
+    lead_pawn: [[u64; 64]; 6],
+    lead_pawns_size: [[u64; 4]; 6],
+}
+
+static INDICES: LazyLock<Indices> = LazyLock::new(|| {
+    let mut ix = Indices {
+        triangle: [0; 64],
+        lower: [0; 64],
+        kings: [[0; 64]; 10],
+        pawns: [0; 64],
+        lead_pawn: [[0; 64]; 6],
+        lead_pawns_size: [[0; 4]; 6],
+    };
+
+    let mut code = 0;
+    for sq in (0..64).filter(|&sq| off_diagonal(sq) < 0) {
+        ix.lower[sq] = code;
+        code += 1;
+    }
+    for sq in (0..64).filter(|&sq| off_diagonal(sq) == 0) {
+        ix.lower[sq] = code;
+        code += 1;
+    }
+
+    let in_triangle = (0..28).filter(|&sq| sq & 7 <= 3);
+    let mut code = 0;
+    for sq in in_triangle.clone().filter(|&sq| off_diagonal(sq) < 0) {
+        ix.triangle[sq] = code;
+        code += 1;
+    }
+    for sq in in_triangle.clone().filter(|&sq| off_diagonal(sq) == 0) {
+        ix.triangle[sq] = code;
+        code += 1;
+    }
+
+    let mut code = 0;
+    let mut both_diagonal = vec![];
+    for first in 0..10 {
+        let k1 = in_triangle
+            .clone()
+            .find(|&sq| ix.triangle[sq] == first && off_diagonal(sq) <= 0)
+            .unwrap();
+        for k2 in 0..64 {
+            let adjacent =
+                (k1 as i32 & 7).abs_diff(k2 as i32 & 7) <= 1 && (k1 >> 3).abs_diff(k2 >> 3) <= 1;
+            if adjacent || (off_diagonal(k1) == 0 && off_diagonal(k2) > 0) {
+                continue;
+            }
+            if off_diagonal(k1) == 0 && off_diagonal(k2) == 0 {
+                both_diagonal.push((first, k2));
+            } else {
+                ix.kings[first as usize][k2] = code;
+                code += 1;
+            }
+        }
+    }
+    for (first, k2) in both_diagonal {
+        ix.kings[first as usize][k2] = code;
+        code += 1;
+    }
+
+    let mut available = 48;
+    for count in 1..6 {
+        for file in 0..4 {
+            let mut idx = 0;
+            for rank in 1..7 {
+                let sq = rank * 8 + file;
+                if count == 1 {
+                    available -= 2;
+                    ix.pawns[sq] = available + 1;
+                    ix.pawns[sq ^ 7] = available;
+                }
+                ix.lead_pawn[count][sq] = idx;
+                idx += binomial(ix.pawns[sq], count as u64 - 1);
+            }
+            ix.lead_pawns_size[count][file] = idx;
+        }
+    }
+
+    ix
+});
+
+/// One compressed subtable, for a side to move and, with pawns, a file
+/// of the leading pawn. Positions are ordered by `groups` and stored as
+/// Huffman-coded symbols, each expanding into a run of values.
+#[derive(Debug, Clone, Default)]
+struct Pairs {
+    flags: u8,
+    /// Piece codes as in the file: the piece number, plus 8 for black
+    pieces: Vec<u8>,
+    /// Sizes of the groups of pieces indexed together, with their factors
+    groups: Vec<(usize, u64)>,
+    size: u64,
+    block_size: usize,
+    span: u64,
+    blocks: usize,
+    block_lengths_len: usize,
+    min_len: u8,
+    lowest: usize,
+    base: Vec<u64>,
+    lengths: Vec<u32>,
+    tree: usize,
+    sparse: usize,
+    block_lengths: usize,
+    data: usize,
+    /// Where the DTZ value maps for each outcome start
+    map: [usize; 4],
+}
+
+/// A whole `.rtbw` or `.rtbz` file, with offsets into its bytes
+#[derive(Debug, Clone)]
+struct Table {
+    key: String,
+    bytes: Vec<u8>,
+    dtz: bool,
+    symmetric: bool,
+    pawns: bool,
+    /// Any piece but a king without a twin of its color
+    unique: bool,
+    /// Whether both sides have pawns
+    both_pawns: bool,
+    /// Indexed by leading pawn file, then side to move
+    pairs: Vec<Vec<Pairs>>,
+    map: usize,
+}
+
+impl Table {
+    fn new(key: &str, bytes: Vec<u8>, dtz: bool) -> Result<Self, String> {
+        let (white, black) = key.split_once('v').ok_or("Bad table name")?;
+        let pawns = [white.matches('P').count(), black.matches('P').count()];
+        let unique = [white, black].iter().any(|side| {
+            side.chars()
+                .filter(|&c| c != 'K')
+                .any(|c| side.matches(c).count() == 1)
+        });
+
+        let mut table = Self {
+            key: key.to_string(),
+            bytes,
+            dtz,
+            symmetric: white == black,
+            pawns: pawns[0] + pawns[1] > 0,
+            unique,
+            both_pawns: pawns[0] > 0 && pawns[1] > 0,
+            pairs: vec![],
+            map: 0,
+        };
+
+        if table.byte(4) & HAS_PAWNS != 0 && !table.pawns {
+            return Err("Pawn flag does not match the name".to_string());
+        }
+
+        let count = key.len() - 1;
+        let sides = if dtz || table.symmetric { 1 } else { 2 };
+        let files = if table.pawns { 4 } else { 1 };
+        let both = table.both_pawns as usize;
+
+        let mut pos = 5;
+        for file in 0..files {
+            let order = [
+                [
+                    table.byte(pos) & 0xf,
+                    if both == 1 {
+                        table.byte(pos + 1) & 0xf
+                    } else {
+                        0xf
+                    },
+                ],
+                [
+                    table.byte(pos) >> 4,
+                    if both == 1 {
+                        table.byte(pos + 1) >> 4
+                    } else {
+                        0xf
+                    },
+                ],
+            ];
+            pos += 1 + both;
+
+            let mut sides_pairs = vec![];
+            for (side, order) in order.iter().enumerate().take(sides) {
+                let pieces: Vec<u8> = table.bytes[pos.min(table.bytes.len())..]
+                    .iter()
+                    .take(count)
+                    .map(|b| if side == 1 { b >> 4 } else { b & 0xf })
+                    .collect();
+                let mut pairs = Pairs {
+                    pieces,
+                    ..Pairs::default()
+                };
+                table.set_groups(&mut pairs, *order, file);
+                sides_pairs.push(pairs);
+            }
+            pos += count;
+            table.pairs.push(sides_pairs);
+        }
+        pos += pos & 1;
+
+        for file in 0..files {
+            for side in 0..sides {
+                pos = table.set_sizes(file, side, pos);
+            }
+        }
+
+        table.map = pos;
+        if dtz {
+            for file in 0..files {
+                let flags = table.pairs[file][0].flags;
+                if flags & MAPPED == 0 {
+                    continue;
+                }
+                for i in 0..4 {
+                    if flags & WIDE != 0 {
+                        pos += pos & 1;
+                        table.pairs[file][0].map[i] = (pos - table.map) / 2 + 1;
+                        pos += 2 * table.u16_le(pos) as usize + 2;
+                    } else {
+                        table.pairs[file][0].map[i] = pos - table.map + 1;
+                        pos += table.byte(pos) as usize + 1;
+                    }
+                }
+            }
+            pos += pos & 1;
+        }
+
+        for file in 0..files {
+            for pairs in &mut table.pairs[file] {
+                pairs.sparse = pos;
+                if pairs.span > 0 {
+                    pos += pairs.size.div_ceil(pairs.span) as usize * 6;
+                }
+            }
+        }
+        for file in 0..files {
+            for pairs in &mut table.pairs[file] {
+                pairs.block_lengths = pos;
+                pos += pairs.block_lengths_len * 2;
+            }
+        }
+        for file in 0..files {
+            for pairs in table.pairs[file].iter_mut().filter(|p| p.blocks > 0) {
+                pos = pos.next_multiple_of(64);
+                pairs.data = pos;
+                pos += pairs.blocks * pairs.block_size;
+            }
+        }
+
+        if pos > table.bytes.len() {
+            return Err("Truncated table".to_string());
+        }
+        Ok(table)
+    }
+
+    /// Splits the pieces into groups and works out each group's factor in the index
+    fn set_groups(&self, pairs: &mut Pairs, order: [u8; 2], file: usize) {
+        let mut lens = vec![1];
+        let mut leading = if self.pawns {
+            0
+        } else if self.unique {
+            3
+        } else {
+            2
+        };
+        for i in 1..pairs.pieces.len() {
+            leading -= 1;
+            if leading > 0 || pairs.pieces[i] == pairs.pieces[i - 1] {
+                *lens.last_mut().unwrap() += 1;
+            } else {
+                lens.push(1);
+            }
+        }
+
+        let mut factors = vec![0; lens.len()];
+        let mut next = if self.both_pawns { 2 } else { 1 };
+        let mut free = 64 - lens[0] - if self.both_pawns { lens[1] } else { 0 };
+        let mut idx = 1;
+        let mut k = 0;
+        while next < lens.len() || k == order[0] || k == order[1] {
+            if k == order[0] {
+                factors[0] = idx;
+                idx *= if self.pawns {
+                    INDICES.lead_pawns_size[lens[0]][file]
+                } else if self.unique {
+                    31332
+                } else {
+                    462
+                };
+            } else if k == order[1] {
+                factors[1] = idx;
+                idx *= binomial(48 - lens[0] as u64, lens[1] as u64);
+            } else {
+                factors[next] = idx;
+                idx *= binomial(free as u64, lens[next] as u64);
+                free -= lens[next];
+                next += 1;
+            }
+            k += 1;
+        }
+
+        pairs.groups = lens.into_iter().zip(factors).collect();
+        pairs.size = idx;
+    }
+
+    /// Reads the block layout and Huffman code of a subtable
+    fn set_sizes(&mut self, file: usize, side: usize, mut pos: usize) -> usize {
+        let flags = self.byte(pos);
+        let mut pairs = std::mem::take(&mut self.pairs[file][side]);
+        pairs.flags = flags;
+
+        if flags & SINGLE_VALUE != 0 {
+            pairs.min_len = self.byte(pos + 1);
+            self.pairs[file][side] = pairs;
+            return pos + 2;
+        }
+
+        pairs.block_size = 1 << self.byte(pos + 1);
+        pairs.span = 1 << self.byte(pos + 2);
+        pairs.blocks = self.u32_le(pos + 4) as usize;
+        pairs.block_lengths_len = pairs.blocks + self.byte(pos + 3) as usize;
+        let max_len = self.byte(pos + 8);
+        pairs.min_len = self.byte(pos + 9);
+        pos += 10;
+
+        pairs.lowest = pos;
+        let lens = (max_len as usize + 1).saturating_sub(pairs.min_len as usize);
+        let mut base = vec![0u64; lens];
+        for i in (0..lens.saturating_sub(1)).rev() {
+            let lowest = self.u16_le(pos + 2 * i) as u64;
+            let above = self.u16_le(pos + 2 * i + 2) as u64;
+            base[i] = (base[i + 1] + lowest).wrapping_sub(above) / 2;
+        }
+        for (i, b) in base.iter_mut().enumerate() {
+            *b = b
+                .checked_shl(64 - i as u32 - pairs.min_len as u32)
+                .unwrap_or(0);
+        }
+        pairs.base = base;
+        pos += lens * 2;
+
+        let symbols = self.u16_le(pos) as usize;
+        pos += 2;
+        pairs.tree = pos;
+        let mut lengths = vec![None; symbols];
+        for sym in 0..symbols {
+            self.symbol_length(pairs.tree, sym, &mut lengths);
+        }
+        pairs.lengths = lengths.into_iter().map(Option::unwrap_or_default).collect();
+
+        self.pairs[file][side] = pairs;
+        pos + symbols * 3 + (symbols & 1)
+    }
+
+    /// How many values past the first a symbol expands into
+    fn symbol_length(&self, tree: usize, sym: usize, lengths: &mut [Option<u32>]) -> u32 {
+        if let Some(len) = lengths.get(sym).copied().flatten() {
+            return len;
+        }
+
+        let (left, right) = self.children(tree, sym);
+        let len = if right == 0xfff {
+            0
+        } else {
+            self.symbol_length(tree, left, lengths) + self.symbol_length(tree, right, lengths) + 1
+        };
+        if let Some(slot) = lengths.get_mut(sym) {
+            *slot = Some(len);
+        }
+        len
+    }
+
+    /// The pair a symbol stands for, as two 12-bit symbols
+    fn children(&self, tree: usize, sym: usize) -> (usize, usize) {
+        let [a, b, c] = [0, 1, 2].map(|i| self.byte(tree + 3 * sym + i) as usize);
+        (((b & 0xf) << 8) | a, (c << 4) | (b >> 4))
+    }
+
+    /// The stored value for `board`, mapped for a DTZ table whose
+    /// position has outcome `wdl`, or `None` when the DTZ table only
+    /// has the other side to move
+    fn value(&self, board: &BitBoard, wdl: Wdl) -> Option<i32> {
+        let black = board.metadata.to_move == Color::Black;
+        let flip = (self.symmetric && black) || material_key(board) != self.key;
+        let side = (flip ^ black) as usize;
+
+        let mut pieces = vec![];
+        for (color, half) in [(Color::White, &board.white), (Color::Black, &board.black)] {
+            for &piece in ChessPiece::VARIANTS {
+                let mut mask = half.mask(piece);
+                while mask != 0 {
+                    let sq = mask.trailing_zeros() as usize;
+                    mask &= mask - 1;
+                    let black = (color == Color::Black) ^ flip;
+                    pieces.push((
+                        piece as u8 | (black as u8) << 3,
+                        sq ^ if flip { 56 } else { 0 },
+                    ));
+                }
+            }
+        }
+        pieces.sort_by_key(|&(_, sq)| sq ^ if flip { 56 } else { 0 });
+
+        let mut lead = 0;
+        let mut file = 0;
+        if self.pawns {
+            let code = self.pairs[0][0].pieces[0];
+            pieces.sort_by_key(|&(c, _)| c != code);
+            lead = pieces.iter().filter(|&&(c, _)| c == code).count();
+            let first = (0..lead)
+                .max_by_key(|&i| INDICES.pawns[pieces[i].1])
+                .unwrap_or(0);
+            pieces.swap(0, first);
+            file = (pieces[0].1 & 7).min(7 - (pieces[0].1 & 7));
+        }
+
+        let pairs = &self.pairs[file][if self.dtz { 0 } else { side }];
+        if self.dtz && (pairs.flags & STM) as usize != side && (!self.symmetric || self.pawns) {
+            return None;
+        }
+
+        for i in lead..pieces.len().saturating_sub(1) {
+            if let Some(j) = (i + 1..pieces.len()).find(|&j| pieces[j].0 == pairs.pieces[i]) {
+                pieces.swap(i, j);
+            }
+        }
+
+        let mut squares: Vec<usize> = pieces.iter().map(|&(_, sq)| sq).collect();
+        if squares[0] & 7 > 3 {
+            squares.iter_mut().for_each(|sq| *sq ^= 7);
+        }
+
+        let mut idx = if self.pawns {
+            squares[1..lead].sort_by_key(|&sq| INDICES.pawns[sq]);
+            (1..lead).fold(INDICES.lead_pawn[lead][squares[0]], |idx, i| {
+                idx + binomial(INDICES.pawns[squares[i]], i as u64)
+            })
+        } else {
+            self.piece_index(&mut squares, pairs.groups[0].0)
+        };
+
+        idx *= pairs.groups[0].1;
+        let mut start = pairs.groups[0].0;
+        let mut pawns = self.both_pawns;
+        for &(len, factor) in &pairs.groups[1..] {
+            squares[start..start + len].sort();
+            let mut n = 0;
+            for i in 0..len {
+                let sq = squares[start + i];
+                let below = squares[..start].iter().filter(|&&other| other < sq).count();
+                n += binomial((sq - below - 8 * pawns as usize) as u64, i as u64 + 1);
+            }
+            idx += n * factor;
+            start += len;
+            pawns = false;
+        }
+
+        let value = self.decompress(pairs, idx) as i32;
+        Some(if self.dtz {
+            self.map_dtz(&self.pairs[file][0], value, wdl)
+        } else {
+            value
+        })
+    }
+
+    /// Index of the leading group without pawns, mirroring the first
+    /// piece into the a1-d1-d4 triangle
+    fn piece_index(&self, squares: &mut [usize], leading: usize) -> u64 {
+        if squares[0] >> 3 > 3 {
+            squares.iter_mut().for_each(|sq| *sq ^= 56);
+        }
+
+        if let Some(i) = (0..leading).find(|&i| off_diagonal(squares[i]) != 0)
+            && off_diagonal(squares[i]) > 0
+        {
+            squares[i..]
+                .iter_mut()
+                .for_each(|sq| *sq = ((*sq >> 3) | (*sq << 3)) & 63);
+        }
+
+        let ix = &*INDICES;
+        if !self.unique {
+            return ix.kings[ix.triangle[squares[0]] as usize][squares[1]];
+        }
+
+        let [s0, s1, s2] = [squares[0], squares[1], squares[2]];
+        let adjust1 = (s1 > s0) as usize;
+        let adjust2 = (s2 > s0) as usize + (s2 > s1) as usize;
+        let rank = |sq: usize| (sq >> 3) as u64;
+
+        if off_diagonal(s0) != 0 {
+            (ix.triangle[s0] * 63 + (s1 - adjust1) as u64) * 62 + (s2 - adjust2) as u64
+        } else if off_diagonal(s1) != 0 {
+            (6 * 63 + rank(s0) * 28 + ix.lower[s1]) * 62 + (s2 - adjust2) as u64
+        } else if off_diagonal(s2) != 0 {
+            6 * 63 * 62
+                + 4 * 28 * 62
+                + rank(s0) * 7 * 28
+                + (rank(s1) - adjust1 as u64) * 28
+                + ix.lower[s2]
+        } else {
+            6 * 63 * 62
+                + 4 * 28 * 62
+                + 4 * 7 * 28
+                + rank(s0) * 7 * 6
+                + (rank(s1) - adjust1 as u64) * 6
+                + (rank(s2) - adjust2 as u64)
+        }
+    }
+
+    /// The plies to zeroing for a stored DTZ value
+    fn map_dtz(&self, pairs: &Pairs, mut value: i32, wdl: Wdl) -> i32 {
+        if pairs.flags & MAPPED != 0 {
+            let map = pairs.map[[1, 3, 0, 2, 0][(wdl as i8 + 2) as usize]];
+            value = if pairs.flags & WIDE != 0 {
+                self.u16_le(self.map + 2 * (map + value as usize)) as i32
+            } else {
+                self.byte(self.map + map + value as usize) as i32
+            };
+        }
+
+        let plies = match wdl {
+            Wdl::Win => pairs.flags & WIN_PLIES != 0,
+            Wdl::Loss => pairs.flags & LOSS_PLIES != 0,
+            _ => false,
+        };
+        if !plies {
+            value *= 2;
+        }
+        value + 1
+    }
+
+    /// The value at `idx`: find its block through the sparse index, then
+    /// walk the block's symbols and expand the one covering it
+    fn decompress(&self, pairs: &Pairs, idx: u64) -> u16 {
+        if pairs.flags & SINGLE_VALUE != 0 {
+            return pairs.min_len as u16;
+        }
+
+        let k = (idx / pairs.span) as usize;
+        let mut block = self.u32_le(pairs.sparse + 6 * k) as usize;
+        let mut offset = self.u16_le(pairs.sparse + 6 * k + 4) as i64;
+        offset += (idx % pairs.span) as i64 - (pairs.span / 2) as i64;
+
+        let block_length = |block: usize| self.u16_le(pairs.block_lengths + 2 * block) as i64;
+        while offset < 0 {
+            block -= 1;
+            offset += block_length(block) + 1;
+        }
+        while offset > block_length(block) {
+            offset -= block_length(block) + 1;
+            block += 1;
+        }
+
+        let mut pos = pairs.data + block * pairs.block_size;
+        let mut buf = self.u64_be(pos);
+        let mut bits = 64;
+        pos += 8;
+
+        let min_len = pairs.min_len as u32;
+        let mut sym = loop {
+            let len = pairs.base.iter().position(|&b| buf >= b).unwrap_or(0);
+            let sym = ((buf - pairs.base[len]) >> (64 - len as u32 - min_len)) as usize
+                + self.u16_le(pairs.lowest + 2 * len) as usize;
+            let run = pairs.lengths.get(sym).copied().unwrap_or(0) as i64 + 1;
+            if offset < run {
+                break sym;
+            }
+
+            offset -= run;
+            let len = len as u32 + min_len;
+            buf <<= len;
+            bits -= len;
+            if bits <= 32 {
+                bits += 32;
+                buf |= (self.u32_be(pos) as u64) << (64 - bits);
+                pos += 4;
+            }
+        };
+
+        while pairs.lengths.get(sym).is_some_and(|&len| len > 0) {
+            let (left, right) = self.children(pairs.tree, sym);
+            let run = pairs.lengths[left] as i64 + 1;
+            if offset < run {
+                sym = left;
+            } else {
+                offset -= run;
+                sym = right;
+            }
+        }
+
+        self.children(pairs.tree, sym).0 as u16
+    }
+
+    fn byte(&self, pos: usize) -> u8 {
+        self.bytes.get(pos).copied().unwrap_or(0)
+    }
+
+    fn read<const N: usize>(&self, pos: usize) -> [u8; N] {
+        std::array::from_fn(|i| self.byte(pos + i))
+    }
+
+    fn u16_le(&self, pos: usize) -> u16 {
+        u16::from_le_bytes(self.read(pos))
+    }
+
+    fn u32_le(&self, pos: usize) -> u32 {
+        u32::from_le_bytes(self.read(pos))
+    }
+
+    fn u32_be(&self, pos: usize) -> u32 {
+        u32::from_be_bytes(self.read(pos))
+    }
+
+    fn u64_be(&self, pos: usize) -> u64 {
+        u64::from_be_bytes(self.read(pos))
+    }
+}
@@ -0,0 +1,578 @@
+use std::{path::PathBuf, sync::Arc};
+
+use rand::{Rng, rngs::SmallRng};
+use strum::VariantArray;
+
+use crate::{
+    arrays::ArrayBoard,
+    bits::{
+        board::{BitBoard, HalfBitBoard},
+        exchange::piece_value,
+    },
+    fuzzing::pi_rng,
+    model::{
+        BoardRank, ChessPiece, Color, DrawReason, Square, Victory, WinReason,
+        castling::{CLASSIC_CASTLING, CastlingRights},
+    },
+    notation::fen::{parse_fen, render_fen},
+    search::{Search, SearchLimits},
+    tablebase::{
+        Dtz, Tablebase, Wdl,
+        retro::{Dtm, DtmTable, Material, RetroTablebase},
+        syzygy::{DTZ_MAGIC, Syzygy, WDL_MAGIC, material_key},
+    },
+    zobrist::table::ZobTable,
+};
+
+/// Calls a position won once a side is a rook or more ahead
+#[cfg(test)]
+struct MaterialTablebase;
+
+#[cfg(test)]
+fn material(half: &HalfBitBoard) -> i32 {
+    ChessPiece::VARIANTS
+        .iter()
+        .map(|p| half.mask(*p).count_ones() as i32 * piece_value(*p) as i32)
+        .sum()
+}
+
+#[cfg(test)]
+impl Tablebase for MaterialTablebase {
+    fn max_pieces(&self) -> u32 {
+        32
+    }
+
+    fn probe_wdl(&self, board: &BitBoard) -> Result<Wdl, String> {
+        let (active, passive) = board.active_passive(board.metadata.to_move);
+        Ok(match material(active) - material(passive) {
+            500.. => Wdl::Win,
+            ..=-500 => Wdl::Loss,
+            _ => Wdl::Draw,
+        })
+    }
+
+    fn probe_dtz(&self, _: &BitBoard) -> Result<Dtz, String> {
+        Err("no DTZ".to_string())
+    }
+}
+
+#[cfg(test)]
+fn table_dir(name: &str, files: &[(&str, &[u8])]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mintymacks-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    for (file, contents) in files {
+        std::fs::write(dir.join(file), contents).unwrap();
+    }
+    dir
+}
+
+#[test]
+fn syzygy_material_keys() {
+    let board = parse_fen("8/8/4k3/8/2P5/8/4r3/R3K3 w - - 0 1").unwrap();
+    assert_eq!(material_key(&board), "KRPvKR");
+    assert_eq!(
+        material_key(&BitBoard::startpos()),
+        "KQRRBBNNPPPPPPPPvKQRRBBNNPPPPPPPP"
+    );
+}
+
+#[cfg(test)]
+fn syzygy_fixtures() -> Syzygy {
+    Syzygy::open(&PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/syzygy")).unwrap()
+}
+
+#[test]
+fn syzygy_open() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/syzygy");
+    let tb = syzygy_fixtures();
+    assert_eq!(tb.max_pieces, 4);
+    assert_eq!(tb.wdl.len(), 13);
+    assert_eq!(tb.dtz.len(), 9);
+
+    let kqk = parse_fen("8/8/4k3/8/8/8/8/3QK3 w - - 0 1").unwrap();
+    assert_eq!(tb.wdl_path(&kqk), Ok(dir.join("KQvK.rtbw").as_path()));
+    assert_eq!(tb.dtz_path(&kqk), Ok(dir.join("KQvK.rtbz").as_path()));
+
+    let mirrored = parse_fen("8/8/4k3/8/8/8/8/3qK3 w - - 0 1").unwrap();
+    assert_eq!(tb.wdl_path(&mirrored), Ok(dir.join("KQvK.rtbw").as_path()));
+
+    let missing = parse_fen("8/8/8/8/8/2k5/2N5/K1B5 w - - 0 1").unwrap();
+    assert!(!tb.covers(&missing));
+    assert!(tb.wdl_path(&missing).is_err());
+    assert!(tb.probe_wdl(&missing).is_err());
+
+    let wdl_only = parse_fen("k7/8/8/8/8/8/8/KRQ5 w - - 0 1").unwrap();
+    assert_eq!(tb.probe_wdl(&wdl_only), Ok(Wdl::Win));
+    assert_eq!(
+        tb.dtz_path(&wdl_only),
+        Err("No Syzygy table for KQRvK".to_string())
+    );
+    assert!(tb.probe_dtz(&wdl_only).is_err());
+
+    assert!(tb.wdl_path(&BitBoard::startpos()).is_err());
+    assert!(!tb.covers(&parse_fen("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1").unwrap()));
+}
+
+/// Positions with their WDL and DTZ from the reference prober
+#[test]
+fn syzygy_probe() {
+    let tb = syzygy_fixtures();
+    for (fen, wdl, dtz) in [
+        ("8/4r3/8/K7/4R3/8/8/5k2 w - -", Wdl::Win, 1),
+        ("8/8/R2k4/8/8/K7/8/4r3 b - -", Wdl::Draw, 0),
+        ("8/8/8/8/5k2/3Kb3/8/1N6 w - -", Wdl::Draw, 0),
+        ("8/8/K4k2/7q/8/8/7Q/8 b - -", Wdl::Win, 1),
+        ("K7/8/5r2/8/3k4/5p2/8/8 w - -", Wdl::Loss, -2),
+        ("1r4k1/8/8/p7/8/8/8/K7 b - -", Wdl::Win, 1),
+        ("8/4P3/1k2K3/8/8/8/8/6R1 b - -", Wdl::Loss, -2),
+        ("8/5p2/6k1/K7/8/8/8/8 w - -", Wdl::Loss, -2),
+        ("8/k7/8/3q4/8/7Q/2K5/8 b - -", Wdl::Draw, 0),
+        ("8/8/Q1q5/8/8/2k5/4K3/8 w - -", Wdl::Win, 1),
+        ("8/8/8/2K5/5kp1/8/8/8 b - -", Wdl::Win, 1),
+        ("1k6/1R3P2/8/8/8/8/8/6K1 b - -", Wdl::Loss, -2),
+        ("8/7k/K7/6r1/8/5R2/8/8 w - -", Wdl::Draw, 0),
+        ("8/8/8/2R5/1K6/8/5k2/8 w - -", Wdl::Win, 21),
+        ("8/3k4/8/8/8/8/4P3/3K4 w - -", Wdl::Draw, 0),
+        ("6k1/8/8/8/8/4n3/8/K7 b - -", Wdl::Draw, 0),
+    ] {
+        let board = parse_fen(&format!("{fen} 0 1")).unwrap();
+        assert_eq!(tb.probe_wdl(&board), Ok(wdl), "{fen}");
+        assert_eq!(tb.probe_dtz(&board), Ok(dtz), "{fen}");
+    }
+}
+
+/// Syzygy and the generated tables agree on who wins without pawns
+/// promoting, where the fifty-move rule never matters
+#[test]
+fn syzygy_matches_retro() {
+    let tb = syzygy_fixtures();
+    let mut retro = RetroTablebase::default();
+    let mut rng = pi_rng();
+
+    for key in ["KRvK", "KPvK"] {
+        let table = retro.generate(key).unwrap().clone();
+        let mut checked = 0;
+        while checked < 300 {
+            let Some(board) = random_position(&mut rng, &table.material) else {
+                continue;
+            };
+            if (board.white.pawns | board.black.pawns) & 0xff000000000000ff != 0 {
+                continue;
+            }
+            checked += 1;
+
+            let expected = match retro.probe_dtm(&board).unwrap() {
+                Dtm::Win(_) => Wdl::Win,
+                Dtm::Draw => Wdl::Draw,
+                Dtm::Loss(_) => Wdl::Loss,
+            };
+            let dtz = tb.probe_dtz(&board).unwrap();
+            assert_eq!(tb.probe_wdl(&board), Ok(expected), "{}", render_fen(&board));
+            assert_eq!(
+                dtz.signum(),
+                (expected as Dtz).signum(),
+                "{}",
+                render_fen(&board)
+            );
+        }
+    }
+}
+
+#[test]
+fn syzygy_adjudication_and_search() {
+    let tb = Arc::new(syzygy_fixtures());
+
+    let won = parse_fen("8/8/8/2R5/1K6/8/5k2/8 b - - 0 1").unwrap();
+    let drawn = parse_fen("8/7k/K7/6r1/8/5R2/8/8 w - - 0 1").unwrap();
+    assert_eq!(
+        Victory::adjudicate(&won, tb.as_ref()),
+        Some(Victory::WhiteWins(WinReason::Adjudication))
+    );
+    assert_eq!(
+        Victory::adjudicate(&drawn, tb.as_ref()),
+        Some(Victory::Draw(DrawReason::Adjudication))
+    );
+
+    // Only taking the queen keeps the win
+    let board = parse_fen("4k3/8/8/8/8/8/3q4/3QK3 w - - 0 1").unwrap();
+    let res = Search::new(
+        board,
+        SearchLimits::depth(1),
+        Arc::new(ZobTable::megabytes(1)),
+    )
+    .with_multipv(64)
+    .with_tablebase(tb)
+    .run(|_| {});
+
+    assert_eq!(res.lines.len(), 2);
+    for line in &res.lines {
+        assert_eq!(line.pv[0].pmv.to, Square::d2);
+    }
+}
+
+#[test]
+fn syzygy_open_errors() {
+    assert!(Syzygy::open(&std::env::temp_dir().join("mintymacks-no-such-dir")).is_err());
+
+    let dir = table_dir("syzygy-magic", &[("KQvK.rtbw", &DTZ_MAGIC)]);
+    assert!(Syzygy::open(&dir).unwrap_err().contains("bad Syzygy magic"));
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let dir = table_dir("syzygy-short", &[("KQvK.rtbw", &[0x71])]);
+    assert!(Syzygy::open(&dir).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    for name in ["KQK.rtbw", "QKvK.rtbw", "KPQvK.rtbw", "KQvKK.rtbw"] {
+        let dir = table_dir("syzygy-name", &[(name, &WDL_MAGIC)]);
+        assert!(Syzygy::open(&dir).is_err(), "{name}");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
+
+#[test]
+fn tablebase_adjudication() {
+    let white = parse_fen("4k3/8/8/8/8/8/8/3QK3 w - - 0 1").unwrap();
+    let black = parse_fen("4k3/8/8/8/8/8/8/3QK3 b - - 0 1").unwrap();
+    let even = parse_fen("4k3/8/8/8/8/8/3r4/3QK3 w - - 0 1").unwrap();
+
+    assert_eq!(
+        Victory::adjudicate(&white, &MaterialTablebase),
+        Some(Victory::WhiteWins(WinReason::Adjudication))
+    );
+    assert_eq!(
+        Victory::adjudicate(&black, &MaterialTablebase),
+        Some(Victory::WhiteWins(WinReason::Adjudication))
+    );
+    assert_eq!(
+        Victory::adjudicate(&even, &MaterialTablebase),
+        Some(Victory::Draw(DrawReason::Adjudication))
+    );
+    assert_eq!(
+        Victory::adjudicate(&BitBoard::startpos(), &MaterialTablebase),
+        None
+    );
+}
+
+/// Like `MaterialTablebase`, but failing once the white queen has moved
+#[cfg(test)]
+struct QueenlessTablebase;
+
+#[cfg(test)]
+impl Tablebase for QueenlessTablebase {
+    fn max_pieces(&self) -> u32 {
+        32
+    }
+
+    fn probe_wdl(&self, board: &BitBoard) -> Result<Wdl, String> {
+        if board.white.queens != Square::d1.bit() {
+            return Err("queen moved".to_string());
+        }
+        MaterialTablebase.probe_wdl(board)
+    }
+
+    fn probe_dtz(&self, _: &BitBoard) -> Result<Dtz, String> {
+        Err("no DTZ".to_string())
+    }
+}
+
+#[test]
+fn tablebase_search_root() {
+    let board = parse_fen("4k3/8/8/8/8/8/3r4/3QK3 w - - 0 1").unwrap();
+
+    let res = Search::new(
+        board.clone(),
+        SearchLimits::depth(1),
+        Arc::new(ZobTable::megabytes(1)),
+    )
+    .with_multipv(10)
+    .with_tablebase(Arc::new(MaterialTablebase))
+    .run(|_| {});
+
+    assert_eq!(res.lines.len(), 2);
+    for line in &res.lines {
+        assert_eq!(line.pv[0].pmv.to.to_str(), "d2");
+    }
+
+    // Moves that cannot be probed are kept, the known worse ones are not
+    let res = Search::new(
+        board,
+        SearchLimits::depth(1),
+        Arc::new(ZobTable::megabytes(1)),
+    )
+    .with_multipv(64)
+    .with_tablebase(Arc::new(QueenlessTablebase))
+    .run(|_| {});
+
+    let firsts = res.lines.iter().map(|l| l.pv[0].pmv).collect::<Vec<_>>();
+    assert!(firsts.contains(&Square::e1.to(Square::d2)));
+    assert!(firsts.contains(&Square::d1.to(Square::d2)));
+    assert!(firsts.contains(&Square::d1.to(Square::a4)));
+    assert!(!firsts.contains(&Square::e1.to(Square::f1)));
+}
+
+/// Places the material on random squares, if that gives a legal position
+#[cfg(test)]
+fn random_position(rng: &mut SmallRng, material: &Material) -> Option<BitBoard> {
+    let mut board = ArrayBoard::new(None);
+    let pieces = material
+        .white
+        .iter()
+        .map(|p| Color::White.piece(*p))
+        .chain(material.black.iter().map(|p| Color::Black.piece(*p)));
+    for p in pieces {
+        let sq = Square::new(rng.random_range(0..64))?;
+        if board.at(sq).is_some() {
+            return None;
+        }
+        board.set(sq, Some(p));
+    }
+
+    let to_move = if rng.random() {
+        Color::White
+    } else {
+        Color::Black
+    };
+    let board = BitBoard::new(
+        &board,
+        to_move,
+        1,
+        0,
+        CastlingRights::nil(),
+        None,
+        CLASSIC_CASTLING,
+    );
+    let (active, passive) = board.active_passive(to_move);
+    (active.attacks(to_move, passive.total) & passive.kings == 0).then_some(board)
+}
+
+/// The value of a position from the values of its successors
+#[cfg(test)]
+fn dtm_by_search(tb: &RetroTablebase, board: &BitBoard) -> Dtm {
+    let mut moves = vec![];
+    board.moves(&mut moves);
+    if moves.is_empty() {
+        return if board.in_check() {
+            Dtm::Loss(0)
+        } else {
+            Dtm::Draw
+        };
+    }
+
+    let rank = |d: Dtm| match d {
+        Dtm::Win(n) => (2, -(n as i32)),
+        Dtm::Draw => (1, 0),
+        Dtm::Loss(n) => (0, n as i32),
+    };
+    moves
+        .iter()
+        .map(|mv| {
+            let mut after = board.clone();
+            after.apply(*mv);
+            tb.probe_dtm(&after).unwrap().before()
+        })
+        .max_by_key(|d| rank(*d))
+        .unwrap()
+}
+
+#[test]
+fn retro_material() {
+    let m = Material::parse("KRvKNP").unwrap();
+    assert_eq!(m.key(), "KRvKNP");
+    assert_eq!(m.clone().canonical(), m);
+    assert_eq!(Material::parse("KvKQ").unwrap().canonical().key(), "KQvK");
+    assert_eq!(Material::parse("KPBvK").unwrap().key(), "KBPvK");
+    assert_eq!(
+        Material::of(&parse_fen("8/8/4k3/8/2P5/8/4r3/R3K3 w - - 0 1").unwrap()).key(),
+        "KRPvKR"
+    );
+
+    for key in ["KQK", "KQvQ", "KKvK", "KqvK"] {
+        assert!(Material::parse(key).is_err(), "{key}");
+    }
+}
+
+#[test]
+fn retro_generate_mates() {
+    let mut tb = RetroTablebase::default();
+    for (key, longest) in [("KQvK", 19), ("KRvK", 31)] {
+        let table = tb.generate(key).unwrap();
+        let longest_win = table
+            .entries
+            .iter()
+            .filter(|v| (1..=128).contains(*v))
+            .max()
+            .map(|v| 2 * *v as u16 - 1);
+        assert_eq!(longest_win, Some(longest), "{key}");
+    }
+    assert_eq!(tb.tables["KQvK"].entries.len(), 462 * 62 * 2);
+
+    let mated = parse_fen("4k3/4Q3/4K3/8/8/8/8/8 b - - 0 1").unwrap();
+    assert_eq!(tb.probe_dtm(&mated), Ok(Dtm::Loss(0)));
+    let stalemate = parse_fen("k7/2Q5/1K6/8/8/8/8/8 b - - 0 1").unwrap();
+    assert_eq!(tb.probe_dtm(&stalemate), Ok(Dtm::Draw));
+    let mate_in_one = parse_fen("4k3/8/4K3/8/8/8/8/R7 w - - 0 1").unwrap();
+    assert_eq!(tb.probe_dtm(&mate_in_one), Ok(Dtm::Win(1)));
+    let hanging = parse_fen("8/8/8/8/8/7k/1q6/K7 w - - 0 1").unwrap();
+    assert_eq!(tb.probe_dtm(&hanging), Ok(Dtm::Draw));
+    let mirrored = parse_fen("8/8/8/8/8/4k3/8/3rK3 w - - 0 1").unwrap();
+    assert_eq!(
+        tb.probe_dtm(&mirrored),
+        tb.probe_dtm(&parse_fen("3Rk3/8/4K3/8/8/8/8/8 b - - 0 1").unwrap())
+    );
+
+    let mut rng = pi_rng();
+    for key in ["KQvK", "KRvK"] {
+        let table = &tb.tables[key];
+        let mut checked = 0;
+        while checked < 500 {
+            let Some(board) = random_position(&mut rng, &table.material) else {
+                continue;
+            };
+            assert_eq!(
+                tb.probe_dtm(&board).unwrap(),
+                dtm_by_search(&tb, &board),
+                "{}",
+                render_fen(&board)
+            );
+            checked += 1;
+        }
+    }
+}
+
+#[test]
+fn retro_generate_pawns() {
+    let mut tb = RetroTablebase::default();
+    tb.generate("KvKP").unwrap();
+    assert!(tb.tables.contains_key("KPvK"));
+    assert!(tb.tables.contains_key("KQvK"));
+    assert!(tb.tables.contains_key("KNvK"));
+    assert_eq!(tb.tables["KPvK"].entries.len(), 1806 * 48 * 2);
+
+    let win = parse_fen("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1").unwrap();
+    assert_eq!(tb.probe_wdl(&win), Ok(Wdl::Win));
+    let draw = parse_fen("8/8/8/8/8/4k3/4P3/4K3 w - - 0 1").unwrap();
+    assert_eq!(tb.probe_wdl(&draw), Ok(Wdl::Draw));
+    let black = parse_fen("4k3/8/8/8/8/8/4p3/4K3 w - - 0 1").unwrap();
+    assert_eq!(tb.probe_wdl(&black), Ok(Wdl::Draw));
+    let promoting = parse_fen("8/8/8/8/8/8/1k1p4/7K b - - 0 1").unwrap();
+    assert_eq!(tb.probe_wdl(&promoting), Ok(Wdl::Win));
+
+    assert_eq!(
+        Victory::adjudicate(&win, &tb),
+        Some(Victory::WhiteWins(WinReason::Adjudication))
+    );
+    assert_eq!(
+        Victory::adjudicate(&draw, &tb),
+        Some(Victory::Draw(DrawReason::Adjudication))
+    );
+    assert_eq!(Victory::adjudicate(&BitBoard::startpos(), &tb), None);
+    assert!(tb.probe_dtz(&win).is_err());
+    assert!(
+        tb.probe_dtm(&parse_fen("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1").unwrap())
+            .is_err()
+    );
+    assert!(
+        tb.probe_dtm(&parse_fen("4k3/8/8/8/8/8/8/2BBK3 w - - 0 1").unwrap())
+            .is_err()
+    );
+}
+
+#[test]
+fn retro_generate_errors() {
+    let mut tb = RetroTablebase::default();
+    assert!(tb.generate("KQRvKR").unwrap_err().contains("more than 4"));
+    assert!(tb.generate("KQ").is_err());
+    assert!(tb.tables.is_empty());
+}
+
+#[test]
+fn retro_table_files() {
+    let mut tb = RetroTablebase::default();
+    let table = tb.generate("KQvK").unwrap().clone();
+
+    let bytes = table.to_bytes().unwrap();
+    assert_eq!(DtmTable::from_bytes(&bytes).as_ref(), Ok(&table));
+    assert!(bytes.len() < table.entries.len() / 2);
+
+    let dir = table_dir("retro-files", &[]);
+    let path = dir.join("KQvK.mmtb");
+    table.save(&path).unwrap();
+    let mut loaded = RetroTablebase::default();
+    loaded.insert(DtmTable::load(&path).unwrap());
+    let board = parse_fen("8/8/8/4k3/8/8/8/3QK3 b - - 0 1").unwrap();
+    assert_eq!(loaded.probe_dtm(&board), tb.probe_dtm(&board));
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let mut bad = bytes.clone();
+    bad[0] = b'X';
+    assert!(DtmTable::from_bytes(&bad).is_err());
+    let mut bad = bytes.clone();
+    bad[4] = 1;
+    assert!(DtmTable::from_bytes(&bad).is_err());
+    let mut bad = bytes.clone();
+    bad[10] ^= 1;
+    assert!(
+        DtmTable::from_bytes(&bad)
+            .unwrap_err()
+            .contains("Wrong entry count")
+    );
+    assert!(DtmTable::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    assert!(DtmTable::from_bytes(&bytes[..8]).is_err());
+}
+
+/// Slow without optimisations, run with `cargo test --release -- --ignored`
+#[test]
+#[ignore]
+fn retro_generate_four_pieces() {
+    let mut tb = RetroTablebase::default();
+    let table = tb.generate("KBNvK").unwrap();
+    assert_eq!(table.entries.len(), 462 * 62 * 62 * 2);
+    let longest_win = table
+        .entries
+        .iter()
+        .filter(|v| (1..=128).contains(*v))
+        .max()
+        .map(|v| 2 * *v as u16 - 1);
+    assert_eq!(longest_win, Some(65));
+
+    tb.generate("KPvKP").unwrap();
+    let en_passant = [
+        "8/8/8/8/k2pP3/8/8/7K b - e3 0 1",
+        "7k/8/8/3Pp3/8/8/8/K7 w - e6 0 1",
+        "8/8/8/8/1k1pP3/8/8/4K3 b - e3 0 1",
+        "4k3/8/8/2pP4/8/8/8/6K1 w - c6 0 1",
+    ];
+    for fen in en_passant {
+        let board = parse_fen(fen).unwrap();
+        assert_eq!(
+            tb.probe_dtm(&board).unwrap(),
+            dtm_by_search(&tb, &board),
+            "{fen}"
+        );
+    }
+
+    let mut rng = pi_rng();
+    for key in ["KBNvK", "KPvKP"] {
+        let table = &tb.tables[key];
+        let mut checked = 0;
+        while checked < 2000 {
+            let Some(board) = random_position(&mut rng, &table.material) else {
+                continue;
+            };
+            if (board.white.pawns | board.black.pawns)
+                & (BoardRank::_1.mask() | BoardRank::_8.mask())
+                != 0
+            {
+                continue;
+            }
+            assert_eq!(
+                tb.probe_dtm(&board).unwrap(),
+                dtm_by_search(&tb, &board),
+                "{}",
+                render_fen(&board)
+            );
+            checked += 1;
+        }
+    }
+}
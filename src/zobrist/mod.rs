@@ -19,6 +19,7 @@ use crate::{
 };
 
 pub mod table;
+pub mod tests;
 
 pub static ZOBRIST: LazyLock<ZobristBoard> = LazyLock::new(|| ZobristBoard::new());
 
@@ -166,29 +167,11 @@ impl ZobristBoard {
 
     #[inline]
     pub fn delta(&self, mv: ChessMove, details: CastlingDetails) -> ZobHash {
-        let (act, pas) = self.active_passive(mv.cpc.color());
-
-        let movement = match mv.spc {
-            Some(SpecialMove::Promotion(p)) => {
-                act.pawns.at(mv.pmv.from) ^ act.piece(p).at(mv.pmv.to)
-            }
-            Some(SpecialMove::CastlingEastward) => {
-                let cast = details.eastward.reify(mv.cpc.color());
-                act.kings.at2(cast.king_move) ^ act.rooks.at2(cast.rook_move)
-            }
-            Some(SpecialMove::CastlingWestward) => {
-                let cast = details.westward.reify(mv.cpc.color());
-                act.kings.at2(cast.king_move) ^ act.rooks.at2(cast.rook_move)
-            }
-            Some(SpecialMove::Null) => ZobHash::MIN,
-            None => act.piece(mv.cpc.piece()).at2(mv.pmv),
-        };
-
-        let capture = if let (Some(p), Some(sq)) = (mv.cpc.capture(), mv.cap) {
-            pas.piece(p).hash(sq.bit())
-        } else {
-            ZobHash::MIN
+        let mut pieces = HashDelta {
+            zobrist: self,
+            hash: ZobHash::MIN,
         };
+        piece_delta(mv, details, &mut pieces);
 
         let meta = self.metadata.black_to_move
             ^ self.metadata.hash_epc(mv.ep_opening())
@@ -196,7 +179,67 @@ impl ZobristBoard {
             ^ self.metadata.castling.hash(mv.cr)
             ^ self.metadata.castling.hash(mv.castling_change(details));
 
-        movement ^ capture ^ meta
+        pieces.hash ^ meta
+    }
+}
+
+/// Receives the pieces a move puts on or takes off the board,
+/// so everything kept in step with the pieces updates the same way
+pub trait PieceDelta {
+    fn piece(&mut self, c: Color, p: ChessPiece, sq: Square, added: bool);
+
+    #[inline]
+    fn shift(&mut self, c: Color, p: ChessPiece, pm: PseudoMove) {
+        self.piece(c, p, pm.from, false);
+        self.piece(c, p, pm.to, true);
+    }
+}
+
+/// The piece movements of `mv`, as hashed by `ZobristBoard::delta`
+#[inline]
+pub fn piece_delta(mv: ChessMove, details: CastlingDetails, delta: &mut impl PieceDelta) {
+    let c = mv.cpc.color();
+
+    match mv.spc {
+        Some(SpecialMove::Promotion(p)) => {
+            delta.piece(c, ChessPiece::Pawn, mv.pmv.from, false);
+            delta.piece(c, p, mv.pmv.to, true);
+        }
+        Some(SpecialMove::CastlingEastward) => {
+            let cast = details.eastward.reify(c);
+            delta.shift(c, ChessPiece::King, cast.king_move);
+            delta.shift(c, ChessPiece::Rook, cast.rook_move);
+        }
+        Some(SpecialMove::CastlingWestward) => {
+            let cast = details.westward.reify(c);
+            delta.shift(c, ChessPiece::King, cast.king_move);
+            delta.shift(c, ChessPiece::Rook, cast.rook_move);
+        }
+        Some(SpecialMove::Null) => {}
+        None => delta.shift(c, mv.cpc.piece(), mv.pmv),
+    }
+
+    if let (Some(p), Some(sq)) = (mv.cpc.capture(), mv.cap) {
+        delta.piece(c.opposite(), p, sq, false);
+    }
+}
+
+struct HashDelta<'a> {
+    zobrist: &'a ZobristBoard,
+    hash: ZobHash,
+}
+
+impl PieceDelta for HashDelta<'_> {
+    #[inline]
+    fn piece(&mut self, c: Color, p: ChessPiece, sq: Square, _added: bool) {
+        let (half, _) = self.zobrist.active_passive(c);
+        self.hash ^= half.piece(p).at(sq);
+    }
+
+    #[inline]
+    fn shift(&mut self, c: Color, p: ChessPiece, pm: PseudoMove) {
+        let (half, _) = self.zobrist.active_passive(c);
+        self.hash ^= half.piece(p).at2(pm);
     }
 }
 
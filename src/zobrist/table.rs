@@ -1,39 +1,272 @@
 use std::{
-    collections::{HashMap, VecDeque, hash_map::Entry},
     hash::{BuildHasher, Hasher},
+    sync::atomic::{AtomicU8, AtomicU64, Ordering},
 };
 
-use indexmap::IndexMap;
+use crate::{
+    model::{ChessPiece, Square},
+    notation::LongAlg,
+    search::Score,
+    zobrist::ZobHash,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    Exact = 1,
+    Lower = 2,
+    Upper = 3,
+}
 
-use crate::zobrist::ZobHash;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TableEntry {
+    pub best: Option<LongAlg>,
+    pub score: Score,
+    pub depth: u8,
+    pub bound: Bound,
+}
 
-#[derive(Debug, Clone)]
-pub struct ZobTable<T>(HashMap<ZobHash, T, ZobHashing>, VecDeque<ZobHash>);
+impl TableEntry {
+    const fn pack(self, generation: u8) -> u64 {
+        let mv = match self.best {
+            Some((pmv, pr)) => {
+                pmv.from.ix() as u64
+                    | (pmv.to.ix() as u64) << 6
+                    | (match pr {
+                        Some(p) => p as u64,
+                        None => 0,
+                    }) << 12
+            }
+            None => 0,
+        };
 
-impl<T: Default + Clone + Copy> ZobTable<T> {
-    fn per_megabyte() -> usize {
-        (1000 * 1024) / std::mem::size_of::<(ZobHash, ZobHash, T)>()
+        mv | (self.score as i16 as u16 as u64) << 16
+            | (self.depth as u64) << 32
+            | (self.bound as u64) << 40
+            | ((generation & GENERATION_MASK) as u64) << 42
     }
 
-    fn megabytes(mb: usize) -> Self {
-        let mb: usize = 1.max(mb);
-        let entries = mb * Self::per_megabyte();
-        ZobTable(
-            HashMap::with_capacity_and_hasher(entries, ZobHashing),
-            VecDeque::with_capacity(entries),
-        )
+    fn unpack(data: u64) -> Option<(Self, u8)> {
+        let bound = match (data >> 40) & 0b11 {
+            1 => Bound::Exact,
+            2 => Bound::Lower,
+            3 => Bound::Upper,
+            _ => return None,
+        };
+
+        let best = if data & 0x7FFF == 0 {
+            None
+        } else {
+            let from = Square::new((data & 0x3F) as i8)?;
+            let to = Square::new(((data >> 6) & 0x3F) as i8)?;
+            Some((
+                from.to(to),
+                ChessPiece::from_repr(((data >> 12) & 0b111) as i8),
+            ))
+        };
+
+        let entry = TableEntry {
+            best,
+            score: (data >> 16) as u16 as i16 as Score,
+            depth: (data >> 32) as u8,
+            bound,
+        };
+
+        Some((entry, (data >> 42) as u8 & GENERATION_MASK))
     }
+}
+
+const GENERATION_MASK: u8 = 0x3F;
+const BUCKET_SIZE: usize = 4;
+
+/// Keys are stored xor'ed with their data so that a torn write
+/// from another thread reads back as a miss rather than garbage
+#[derive(Default)]
+struct Slot {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+#[derive(Default)]
+#[repr(align(64))]
+struct Bucket([Slot; BUCKET_SIZE]);
+
+/// Fixed-size transposition table shared between search threads
+pub struct ZobTable {
+    buckets: Box<[Bucket]>,
+    generation: AtomicU8,
+}
 
-    pub fn insert(&mut self, k: ZobHash, v: T) {
-        if self.0.len() == self.0.capacity() || self.1.len() == self.1.capacity() {
-            self.0.remove(&self.1.pop_front().unwrap_or(0));
+impl ZobTable {
+    pub fn megabytes(mb: usize) -> Self {
+        let buckets = (mb.max(1) << 20) / size_of::<Bucket>();
+        ZobTable {
+            buckets: (0..buckets).map(|_| Bucket::default()).collect(),
+            generation: AtomicU8::new(0),
         }
-        self.0.insert(k, v);
-        self.1.push_back(k);
     }
 
-    pub fn get(&self, k: ZobHash) -> Option<T> {
-        self.0.get(&k).map(|x| *x)
+    pub fn capacity(&self) -> usize {
+        self.buckets.len() * BUCKET_SIZE
+    }
+
+    pub fn clear(&self) {
+        for slot in self.buckets.iter().flat_map(|b| &b.0) {
+            slot.key.store(0, Ordering::Relaxed);
+            slot.data.store(0, Ordering::Relaxed);
+        }
+        self.generation.store(0, Ordering::Relaxed);
+    }
+
+    /// Ages every stored entry by one search
+    pub fn new_search(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn generation(&self) -> u8 {
+        self.generation.load(Ordering::Relaxed) & GENERATION_MASK
+    }
+
+    fn bucket(&self, k: ZobHash) -> &Bucket {
+        bucket_of(&self.buckets, k)
+    }
+
+    pub fn get(&self, k: ZobHash) -> Option<TableEntry> {
+        for slot in &self.bucket(k).0 {
+            let data = slot.data.load(Ordering::Relaxed);
+            if slot.key.load(Ordering::Relaxed) ^ data == k {
+                return TableEntry::unpack(data).map(|(e, _)| e);
+            }
+        }
+        None
+    }
+
+    /// Overwrites the entry for the same position if the new one is at
+    /// least as useful, otherwise the shallowest and oldest in the bucket
+    pub fn insert(&self, k: ZobHash, mut entry: TableEntry) {
+        let generation = self.generation();
+        let bucket = self.bucket(k);
+
+        let mut victim = &bucket.0[0];
+        let mut victim_worth = i32::MAX;
+
+        for slot in &bucket.0 {
+            let data = slot.data.load(Ordering::Relaxed);
+            let Some((old, old_generation)) = TableEntry::unpack(data) else {
+                victim = slot;
+                break;
+            };
+
+            if slot.key.load(Ordering::Relaxed) ^ data == k {
+                if entry.bound != Bound::Exact
+                    && old_generation == generation
+                    && entry.depth + 2 < old.depth
+                {
+                    return;
+                }
+                if entry.best.is_none() {
+                    entry.best = old.best;
+                }
+                victim = slot;
+                break;
+            }
+
+            let age = generation.wrapping_sub(old_generation) & GENERATION_MASK;
+            let worth = old.depth as i32 - 8 * age as i32;
+            if worth < victim_worth {
+                victim = slot;
+                victim_worth = worth;
+            }
+        }
+
+        let data = entry.pack(generation);
+        victim.key.store(k ^ data, Ordering::Relaxed);
+        victim.data.store(data, Ordering::Relaxed);
+    }
+
+    /// Permill of sampled slots filled during the current search, for UCI `hashfull`
+    pub fn hashfull(&self) -> u64 {
+        let generation = self.generation();
+        let sample = self.capacity().min(1000);
+
+        let used = self
+            .buckets
+            .iter()
+            .flat_map(|b| &b.0)
+            .take(sample)
+            .filter(|slot| {
+                TableEntry::unpack(slot.data.load(Ordering::Relaxed))
+                    .is_some_and(|(_, g)| g == generation)
+            })
+            .count();
+
+        (used * 1000 / sample) as u64
+    }
+}
+
+fn bucket_of(buckets: &[Bucket], k: ZobHash) -> &Bucket {
+    let ix = (k as u128 * buckets.len() as u128) >> 64;
+    &buckets[ix as usize]
+}
+
+/// Fixed-size cache of perft node counts shared between threads,
+/// keyed on the position and the remaining depth
+pub struct PerftTable {
+    buckets: Box<[Bucket]>,
+}
+
+impl PerftTable {
+    pub fn megabytes(mb: usize) -> Self {
+        let buckets = (mb.max(1) << 20) / size_of::<Bucket>();
+        PerftTable {
+            buckets: (0..buckets).map(|_| Bucket::default()).collect(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buckets.len() * BUCKET_SIZE
+    }
+
+    /// Filled slots, counted by a full scan
+    pub fn filled(&self) -> usize {
+        self.buckets
+            .iter()
+            .flat_map(|b| &b.0)
+            .filter(|slot| slot.data.load(Ordering::Relaxed) != 0)
+            .count()
+    }
+
+    pub fn get(&self, k: ZobHash, depth: usize) -> Option<usize> {
+        for slot in &bucket_of(&self.buckets, k).0 {
+            let data = slot.data.load(Ordering::Relaxed);
+            if data as u8 as usize == depth && slot.key.load(Ordering::Relaxed) ^ data == k {
+                return Some((data >> 8) as usize);
+            }
+        }
+        None
+    }
+
+    /// Overwrites an empty slot or the same entry, otherwise the shallowest in the bucket
+    pub fn insert(&self, k: ZobHash, depth: usize, nodes: usize) {
+        let data = (nodes as u64) << 8 | depth as u8 as u64;
+        let bucket = bucket_of(&self.buckets, k);
+
+        let mut victim = &bucket.0[0];
+        let mut victim_depth = u8::MAX;
+
+        for slot in &bucket.0 {
+            let old = slot.data.load(Ordering::Relaxed);
+            if old == 0 || old as u8 == depth as u8 && slot.key.load(Ordering::Relaxed) ^ old == k {
+                victim = slot;
+                break;
+            }
+            if (old as u8) < victim_depth {
+                victim = slot;
+                victim_depth = old as u8;
+            }
+        }
+
+        victim.key.store(k ^ data, Ordering::Relaxed);
+        victim.data.store(data, Ordering::Relaxed);
     }
 }
 
@@ -0,0 +1,87 @@
+use crate::{
+    model::{ChessPiece, Square},
+    search::MATE,
+    zobrist::table::{Bound, TableEntry, ZobTable},
+};
+
+#[cfg(test)]
+fn entry(depth: u8, bound: Bound) -> TableEntry {
+    TableEntry {
+        best: Some((Square::e2.to(Square::e4), None)),
+        score: -150,
+        depth,
+        bound,
+    }
+}
+
+#[test]
+fn table_roundtrip() {
+    let table = ZobTable::megabytes(1);
+    assert_eq!(table.get(0x1234), None);
+
+    table.insert(0x1234, entry(5, Bound::Exact));
+    assert_eq!(table.get(0x1234), Some(entry(5, Bound::Exact)));
+    assert_eq!(table.get(0x4321), None);
+
+    let promotion = TableEntry {
+        best: Some((Square::a7.to(Square::b8), Some(ChessPiece::Knight))),
+        score: MATE - 3,
+        depth: 200,
+        bound: Bound::Lower,
+    };
+    table.insert(0x1234, promotion);
+    assert_eq!(table.get(0x1234), Some(promotion));
+
+    table.clear();
+    assert_eq!(table.get(0x1234), None);
+}
+
+#[test]
+fn table_replacement() {
+    let table = ZobTable::megabytes(1);
+
+    table.insert(7, entry(10, Bound::Lower));
+    table.insert(7, entry(2, Bound::Upper));
+    assert_eq!(table.get(7), Some(entry(10, Bound::Lower)));
+
+    table.insert(
+        7,
+        TableEntry {
+            best: None,
+            ..entry(2, Bound::Exact)
+        },
+    );
+    assert_eq!(table.get(7), Some(entry(2, Bound::Exact)));
+
+    // Small keys all land in the first bucket, where the shallowest entry goes first
+    for (k, depth) in [(1, 8), (2, 3), (3, 9)] {
+        table.insert(k, entry(depth, Bound::Exact));
+    }
+    table.insert(4, entry(6, Bound::Exact));
+    assert_eq!(table.get(7), None);
+    assert!(table.get(2).is_some() && table.get(4).is_some());
+
+    // Entries from earlier searches lose to fresh ones
+    table.new_search();
+    table.new_search();
+    table.insert(5, entry(1, Bound::Exact));
+    assert_eq!(table.get(2), None);
+    assert!(table.get(5).is_some());
+}
+
+#[test]
+fn table_hashfull() {
+    let table = ZobTable::megabytes(1);
+    assert_eq!(table.hashfull(), 0);
+
+    for k in 0..table.capacity() as u64 {
+        table.insert(
+            k.wrapping_mul(0x9E37_79B9_7F4A_7C15),
+            entry(1, Bound::Exact),
+        );
+    }
+    assert!(table.hashfull() > 500);
+
+    table.new_search();
+    assert_eq!(table.hashfull(), 0);
+}